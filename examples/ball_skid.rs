@@ -39,6 +39,8 @@ fn setup_level(
             ..Default::default()
         }));
 
+        // The transform only places the ball when it spawns, physics moves it from then on
+        // through its Position
         commands
             .spawn_bundle(PbrBundle {
                 transform: Transform::from_xyz(0.0, 0.5, 0.0),
@@ -40,6 +40,7 @@ fn setup_level(
             ..Default::default()
         }));
 
+        // Starts where the transform is, to move it later write its Position instead
         commands
             .spawn_bundle(PbrBundle {
                 transform: Transform::from_xyz(0.0, 1.0, 0.0),
@@ -192,6 +192,7 @@ fn enter_play_system(
 
         match config.engine {
             Engine::Crate => {
+                // pos is only read when the body spawns, after that it lives in its Position
                 commands.entity(item).insert(Body {
                     mass: Mass::Value(1.0),
                     ..Default::default()
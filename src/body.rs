@@ -1,67 +1,70 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
-const MAX_ANGULAR_VELOCITY: f32 = 30.0;
+use crate::{to_quaternion, to_vector, Matrix, Quaternion, Real, Vector};
 
+const MAX_ANGULAR_VELOCITY: Real = 30.0;
 
-#[derive(Component, Inspectable)]
+// The inspector only knows the f32 math types
+#[derive(Component, Clone)]
+#[cfg_attr(not(feature = "f64"), derive(Inspectable))]
 pub struct Body {
-    pub linear_velocity: Vec3,
-    pub angular_velocity: Vec3,
+    pub linear_velocity: Vector,
+    pub angular_velocity: Vector,
 
-    #[inspectable(min = 0.0, max = 1.0)]
-    pub elasticity: f32,
-    pub friction: f32,
+    #[cfg_attr(not(feature = "f64"), inspectable(min = 0.0, max = 1.0))]
+    pub elasticity: Real,
+    pub friction: Real,
     pub mass: Mass,
 
     // will be set by collider
-    pub inv_mass: f32,
-    pub center_of_mass: Vec3,
-    pub inertia_tensor: Mat3,
-    pub inverse_inertia_tensor_local: Mat3,
+    pub inv_mass: Real,
+    pub center_of_mass: Vector,
+    pub inertia_tensor: Matrix,
+    pub inverse_inertia_tensor_local: Matrix,
 
     // set each frame
-    pub center_of_mass_world: Vec3,
-    pub inverse_inertia_tensor_world: Mat3,
+    pub center_of_mass_world: Vector,
+    pub inverse_inertia_tensor_world: Matrix,
 }
 
 impl Default for Body {
     fn default() -> Self {
         Body {
-            linear_velocity: Vec3::default(),
-            angular_velocity: Vec3::default(),
+            linear_velocity: Vector::default(),
+            angular_velocity: Vector::default(),
             elasticity: 1.0,
             friction: 0.5,
             mass: Mass::Static,
             inv_mass: 0.0,
-            center_of_mass: Vec3::ZERO,
-            center_of_mass_world: Vec3::ZERO,
-            inertia_tensor: Mat3::IDENTITY,
-            inverse_inertia_tensor_local: Mat3::IDENTITY,
-            inverse_inertia_tensor_world: Mat3::IDENTITY,
+            center_of_mass: Vector::ZERO,
+            center_of_mass_world: Vector::ZERO,
+            inertia_tensor: Matrix::IDENTITY,
+            inverse_inertia_tensor_local: Matrix::IDENTITY,
+            inverse_inertia_tensor_world: Matrix::IDENTITY,
         }
     }
 }
 impl Body {
 
-    pub fn center_of_mass_world(&self, t: &GlobalTransform) -> Vec3 {
-        t.translation + t.rotation * self.center_of_mass
+    pub fn center_of_mass_world(&self, p: &Position) -> Vector {
+        p.translation + p.rotation * self.center_of_mass
     }
-    
-    pub fn world_to_local(&self, t: &GlobalTransform, world_point: Vec3) -> Vec3 {
+
+    pub fn world_to_local(&self, p: &Position, world_point: Vector) -> Vector {
         let tmp = world_point - self.center_of_mass_world;
-        let inv_orientation = t.rotation.conjugate();
+        let inv_orientation = p.rotation.conjugate();
         inv_orientation * tmp
     }
 
-    pub fn apply_impulse_linear(&mut self, impulse: Vec3) {
+    pub fn apply_impulse_linear(&mut self, impulse: Vector) {
         if self.inv_mass == 0.0 {
             return;
         }
         self.linear_velocity += impulse * self.inv_mass;
     }
 
-    pub fn apply_impulse_angular(&mut self, impulse: Vec3) {
+    pub fn apply_impulse_angular(&mut self, impulse: Vector) {
         if self.inv_mass == 0.0 {
             return;
         }
@@ -73,7 +76,7 @@ impl Body {
         }
     }
 
-    pub fn apply_impulse(&mut self, impulse_point: Vec3, impulse: Vec3) {
+    pub fn apply_impulse(&mut self, impulse_point: Vector, impulse: Vector) {
         if self.inv_mass == 0.0 {
             return;
         }
@@ -87,24 +90,28 @@ impl Body {
         self.apply_impulse_angular(dl);
     }
 
-    pub fn update(&mut self, transform: &mut GlobalTransform, dt: f32) {
+    pub fn update(&mut self, position: &mut Position, locked: &LockedAxes, dt: Real) {
+        let mut translation = position.translation;
+        let mut rotation = position.rotation;
+
         // apply linear velocity
-        transform.translation += self.linear_velocity * dt;
+        translation += self.linear_velocity * dt;
 
         // we have an angular velocity around the centre of mass, this needs to be converted to
         // relative body translation. This way we can properly update the rotation of the model
 
         let position_com = self.center_of_mass_world;
-        let com_to_position = transform.translation - position_com;
+        let com_to_position = translation - position_com;
 
         // total torque is equal to external applied torques + internal torque (precession)
         // T = T_external + omega x I * omega
         // T_external = 0 because it was applied in the collision response function
         // T = Ia = w x I * w
         // a = I^-1 (w x I * w)
-        let orientation = Mat3::from_quat(transform.rotation);
+        let orientation = Matrix::from_quat(rotation);
         let inertia_tensor = orientation * self.inertia_tensor * orientation.transpose();
-        let alpha = inertia_tensor.inverse()
+        // Locked axes cant pick up rotation from precession either
+        let alpha = locked.lock_inertia(inertia_tensor.inverse())
             * (self
                 .angular_velocity
                 .cross(inertia_tensor * self.angular_velocity));
@@ -115,29 +122,126 @@ impl Body {
         let angle = d_angle.length();
         let inv_angle = angle.recip();
         let dq = if inv_angle.is_finite() {
-            Quat::from_axis_angle(d_angle * inv_angle, angle)
+            Quaternion::from_axis_angle(d_angle * inv_angle, angle)
         } else {
-            Quat::IDENTITY
+            Quaternion::IDENTITY
         };
-        transform.rotation = (dq * transform.rotation).normalize();
+        rotation = (dq * rotation).normalize();
 
         // now get the new body position
-        transform.translation = position_com + dq * com_to_position;
+        translation = position_com + dq * com_to_position;
 
         // update center of mass and inverse inertia tensor
-        self.center_of_mass_world = transform.translation + transform.rotation * self.center_of_mass;
-        let orientation = Mat3::from_quat(transform.rotation);
-        self.inverse_inertia_tensor_world =
+        self.center_of_mass_world = translation + rotation * self.center_of_mass;
+        let orientation = Matrix::from_quat(rotation);
+        let inverse_inertia =
             orientation * self.inverse_inertia_tensor_local * orientation.transpose();
+        self.inverse_inertia_tensor_world = locked.lock_inertia(inverse_inertia);
+
+        position.translation = translation;
+        position.rotation = rotation;
+    }
+}
+
+/// Where a body is, the simulation reads and writes this instead of the transform so it
+/// keeps full [Real] precision. Taken from the [GlobalTransform] when the body spawns,
+/// after that the transform is only written from it for rendering.
+/// Move bodies by changing this, not their transform
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub translation: Vector,
+    pub rotation: Quaternion,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            translation: Vector::ZERO,
+            rotation: Quaternion::IDENTITY,
+        }
+    }
+}
+
+impl Position {
+    pub fn from_translation(translation: Vector) -> Self {
+        Position {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_transform(transform: &GlobalTransform) -> Self {
+        Position {
+            translation: to_vector(transform.translation),
+            rotation: to_quaternion(transform.rotation),
+        }
     }
 }
 
-#[derive(Component, Inspectable)]
+#[derive(Component, Inspectable, Clone, Copy)]
 pub enum Mass {
     Static,
-    Value(f32),
+    Value(Real),
+}
+
+bitflags::bitflags! {
+    /// Axes a body can't move along or rotate around, in world space.
+    /// Lock all rotation for upright characters, or Z translation for 2.5D games
+    #[derive(Component, Default)]
+    pub struct LockedAxes: u8 {
+        const TRANSLATION_X = 1 << 0;
+        const TRANSLATION_Y = 1 << 1;
+        const TRANSLATION_Z = 1 << 2;
+        const ROTATION_X = 1 << 3;
+        const ROTATION_Y = 1 << 4;
+        const ROTATION_Z = 1 << 5;
+        const TRANSLATION =
+            Self::TRANSLATION_X.bits | Self::TRANSLATION_Y.bits | Self::TRANSLATION_Z.bits;
+        const ROTATION = Self::ROTATION_X.bits | Self::ROTATION_Y.bits | Self::ROTATION_Z.bits;
+    }
 }
 
+impl LockedAxes {
+    // 1 for free axes, 0 for locked ones
+    fn translation_mask(&self) -> Vector {
+        Vector::new(
+            free(self.contains(LockedAxes::TRANSLATION_X)),
+            free(self.contains(LockedAxes::TRANSLATION_Y)),
+            free(self.contains(LockedAxes::TRANSLATION_Z)),
+        )
+    }
 
+    fn rotation_mask(&self) -> Vector {
+        Vector::new(
+            free(self.contains(LockedAxes::ROTATION_X)),
+            free(self.contains(LockedAxes::ROTATION_Y)),
+            free(self.contains(LockedAxes::ROTATION_Z)),
+        )
+    }
 
+    /// Zeroes the locked components of the velocities
+    pub fn lock_velocities(&self, body: &mut Body) {
+        body.linear_velocity *= self.translation_mask();
+        body.angular_velocity *= self.rotation_mask();
+    }
 
+    /// Zeroes the rows and columns of locked rotation axes, so impulses cant turn around them
+    pub fn lock_inertia(&self, inverse_inertia: Matrix) -> Matrix {
+        let mask = Matrix::from_diagonal(self.rotation_mask());
+        mask * inverse_inertia * mask
+    }
+
+    /// Keeps the old value for locked axes
+    pub fn lock_translation(&self, old: Vector, new: Vector) -> Vector {
+        let mask = self.translation_mask();
+        old + (new - old) * mask
+    }
+}
+
+fn free(locked: bool) -> Real {
+    if locked {
+        0.0
+    } else {
+        1.0
+    }
+}
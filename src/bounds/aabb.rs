@@ -1,48 +1,51 @@
 use bevy::{prelude::*, render};
 use bevy_inspector_egui::Inspectable;
 
+use crate::{from_vector, to_vector, Real, Vector};
+
 /// Defines a local axis-aligned bounding box - that is - the bounding box is located at
 /// the Entity transform origin
-#[derive(Debug, Component, Inspectable)]
+#[derive(Debug, Component)]
+#[cfg_attr(not(feature = "f64"), derive(Inspectable))]
 pub struct Aabb {
     /// The coordinates of the point located at the minimum x, y, and z coordinate. This can also
     /// be thought of as the length of the -x, -y, -z axes that extend from the origin and touch
     /// the inside of the bounding box faces.
-    pub minimums: Vec3,
+    pub minimums: Vector,
     /// The coordinates of the point located at the maximum x, y, and z coordinate. This can also
     /// be thought of as the length of the +x, +y, +z axes that extend from the origin and touch
     /// the inside of the bounding box faces.
-    pub maximums: Vec3,
+    pub maximums: Vector,
 }
 
 impl Default for Aabb {
     fn default() -> Self {
         Self {
-            minimums: Vec3::splat(std::f32::MAX),
-            maximums: Vec3::splat(std::f32::MIN),
+            minimums: Vector::splat(Real::MAX),
+            maximums: Vector::splat(Real::MIN),
         }
     }
 }
 
 impl Aabb {
-
     /// Returns the vertices of the bounding box in world space, the current mesh transform.
-    pub fn vertices(&self, transform: &GlobalTransform) -> [Vec3; 8] {
+    pub fn vertices(&self, transform: &GlobalTransform) -> [Vector; 8] {
         let vertices_mesh_space = self.vertices_mesh_space();
+        let translation = to_vector(transform.translation);
         [
-            transform.translation - vertices_mesh_space[0],
-            transform.translation - vertices_mesh_space[1],
-            transform.translation - vertices_mesh_space[2],
-            transform.translation - vertices_mesh_space[3],
-            transform.translation - vertices_mesh_space[4],
-            transform.translation - vertices_mesh_space[5],
-            transform.translation - vertices_mesh_space[6],
-            transform.translation - vertices_mesh_space[7],
+            translation - vertices_mesh_space[0],
+            translation - vertices_mesh_space[1],
+            translation - vertices_mesh_space[2],
+            translation - vertices_mesh_space[3],
+            translation - vertices_mesh_space[4],
+            translation - vertices_mesh_space[5],
+            translation - vertices_mesh_space[6],
+            translation - vertices_mesh_space[7],
         ]
     }
 
     // TODO: we should cache this
-    pub fn vertices_mesh_space(&self) -> [Vec3; 8] {
+    pub fn vertices_mesh_space(&self) -> [Vector; 8] {
         /*
               (2)-----(3)               Y
                | \     | \              |
@@ -53,62 +56,68 @@ impl Aabb {
                   (5)-----(4)
         */
         [
-            Vec3::new(self.maximums.x, self.maximums.y, self.maximums.z), //0
-            Vec3::new(self.minimums.x, self.maximums.y, self.maximums.z), //1
-            Vec3::new(self.minimums.x, self.maximums.y, self.minimums.z), //2
-            Vec3::new(self.maximums.x, self.maximums.y, self.minimums.z), //3
-            Vec3::new(self.maximums.x, self.minimums.y, self.maximums.z), //4
-            Vec3::new(self.minimums.x, self.minimums.y, self.maximums.z), //5
-            Vec3::new(self.minimums.x, self.minimums.y, self.minimums.z), //6
-            Vec3::new(self.maximums.x, self.minimums.y, self.minimums.z), //7
+            Vector::new(self.maximums.x, self.maximums.y, self.maximums.z), //0
+            Vector::new(self.minimums.x, self.maximums.y, self.maximums.z), //1
+            Vector::new(self.minimums.x, self.maximums.y, self.minimums.z), //2
+            Vector::new(self.maximums.x, self.maximums.y, self.minimums.z), //3
+            Vector::new(self.maximums.x, self.minimums.y, self.maximums.z), //4
+            Vector::new(self.minimums.x, self.minimums.y, self.maximums.z), //5
+            Vector::new(self.minimums.x, self.minimums.y, self.minimums.z), //6
+            Vector::new(self.maximums.x, self.minimums.y, self.minimums.z), //7
         ]
     }
 }
 
 /// This will be a valid AABB updated with [GlobalTransform]
-#[derive(Debug, Component, Inspectable)]
+#[derive(Debug, Component, Clone, Copy)]
+#[cfg_attr(not(feature = "f64"), derive(Inspectable))]
 pub struct GlobalAabb {
-    pub minimums: Vec3,
-    pub maximums: Vec3,
+    pub minimums: Vector,
+    pub maximums: Vector,
 }
 
 impl Default for GlobalAabb {
     fn default() -> Self {
         Self {
-            minimums: Vec3::splat(std::f32::MAX),
-            maximums: Vec3::splat(std::f32::MIN),
+            minimums: Vector::splat(Real::MAX),
+            maximums: Vector::splat(Real::MIN),
         }
     }
 }
 
-
 impl GlobalAabb {
-    pub fn from_min_max(minimum: Vec3, maximum: Vec3) -> render::primitives::Aabb {
+    /// False until update_aabb first runs, the default bounds are inverted
+    pub fn is_valid(&self) -> bool {
+        self.minimums.cmple(self.maximums).all()
+    }
+
+    pub fn from_min_max(minimum: Vector, maximum: Vector) -> render::primitives::Aabb {
         let center = 0.5 * (maximum + minimum);
         let half_extents = 0.5 * (maximum - minimum);
         render::primitives::Aabb {
-            center,
-            half_extents,
+            center: from_vector(center),
+            half_extents: from_vector(half_extents),
         }
     }
 
     /// Returns the vertices of the bounding box in world space, the current mesh transform.
-    pub fn vertices(&self, transform: &GlobalTransform) -> [Vec3; 8] {
+    pub fn vertices(&self, transform: &GlobalTransform) -> [Vector; 8] {
         let vertices_mesh_space = self.vertices_mesh_space();
+        let translation = to_vector(transform.translation);
         [
-            transform.translation - vertices_mesh_space[0],
-            transform.translation - vertices_mesh_space[1],
-            transform.translation - vertices_mesh_space[2],
-            transform.translation - vertices_mesh_space[3],
-            transform.translation - vertices_mesh_space[4],
-            transform.translation - vertices_mesh_space[5],
-            transform.translation - vertices_mesh_space[6],
-            transform.translation - vertices_mesh_space[7],
+            translation - vertices_mesh_space[0],
+            translation - vertices_mesh_space[1],
+            translation - vertices_mesh_space[2],
+            translation - vertices_mesh_space[3],
+            translation - vertices_mesh_space[4],
+            translation - vertices_mesh_space[5],
+            translation - vertices_mesh_space[6],
+            translation - vertices_mesh_space[7],
         ]
     }
 
     // TODO: we should cache this
-    pub fn vertices_mesh_space(&self) -> [Vec3; 8] {
+    pub fn vertices_mesh_space(&self) -> [Vector; 8] {
         /*
               (2)-----(3)               Y
                | \     | \              |
@@ -119,14 +128,14 @@ impl GlobalAabb {
                   (5)-----(4)
         */
         [
-            Vec3::new(self.maximums.x, self.maximums.y, self.maximums.z), //0
-            Vec3::new(self.minimums.x, self.maximums.y, self.maximums.z), //1
-            Vec3::new(self.minimums.x, self.maximums.y, self.minimums.z), //2
-            Vec3::new(self.maximums.x, self.maximums.y, self.minimums.z), //3
-            Vec3::new(self.maximums.x, self.minimums.y, self.maximums.z), //4
-            Vec3::new(self.minimums.x, self.minimums.y, self.maximums.z), //5
-            Vec3::new(self.minimums.x, self.minimums.y, self.minimums.z), //6
-            Vec3::new(self.maximums.x, self.minimums.y, self.minimums.z), //7
+            Vector::new(self.maximums.x, self.maximums.y, self.maximums.z), //0
+            Vector::new(self.minimums.x, self.maximums.y, self.maximums.z), //1
+            Vector::new(self.minimums.x, self.maximums.y, self.minimums.z), //2
+            Vector::new(self.maximums.x, self.maximums.y, self.minimums.z), //3
+            Vector::new(self.maximums.x, self.minimums.y, self.maximums.z), //4
+            Vector::new(self.minimums.x, self.minimums.y, self.maximums.z), //5
+            Vector::new(self.minimums.x, self.minimums.y, self.minimums.z), //6
+            Vector::new(self.maximums.x, self.minimums.y, self.minimums.z), //7
         ]
     }
 }
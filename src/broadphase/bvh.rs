@@ -0,0 +1,520 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{Real, Vector};
+
+// Leaves get a bit of slack so small movements dont have to touch the tree
+const AABB_MARGIN: Real = 0.1;
+const NULL_NODE: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    // Fattened for leaves, union of the children otherwise
+    minimums: Vector,
+    maximums: Vector,
+    // Actual bounds, only used by leaves
+    tight_minimums: Vector,
+    tight_maximums: Vector,
+    parent: usize,
+    left: usize,
+    right: usize,
+    // Leaves are 0, free nodes -1
+    height: i32,
+    entity: Option<Entity>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node {
+            minimums: Vector::ZERO,
+            maximums: Vector::ZERO,
+            tight_minimums: Vector::ZERO,
+            tight_maximums: Vector::ZERO,
+            parent: NULL_NODE,
+            left: NULL_NODE,
+            right: NULL_NODE,
+            height: -1,
+            entity: None,
+        }
+    }
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.left == NULL_NODE
+    }
+}
+
+/// Dynamic bounding volume hierarchy, works well when bodies are spread out on every axis.
+/// Leaves are inserted, removed and refit incrementally and the tree is kept balanced with rotations
+pub struct Bvh {
+    nodes: Vec<Node>,
+    root: usize,
+    free: Vec<usize>,
+    leaves: HashMap<Entity, usize>,
+}
+
+impl Default for Bvh {
+    fn default() -> Self {
+        Bvh {
+            nodes: Vec::new(),
+            root: NULL_NODE,
+            free: Vec::new(),
+            leaves: HashMap::default(),
+        }
+    }
+}
+
+impl Bvh {
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Bvh::default();
+    }
+
+    /// Height of the tree, a balanced tree stays close to log2 of the leaf count
+    pub fn height(&self) -> i32 {
+        if self.root == NULL_NODE {
+            0
+        } else {
+            self.nodes[self.root].height
+        }
+    }
+
+    /// Inserts the entity or updates its bounds, the tree is only touched when it leaves its fat bounds
+    pub fn set(&mut self, entity: Entity, minimums: Vector, maximums: Vector) {
+        if let Some(&leaf) = self.leaves.get(&entity) {
+            let node = &mut self.nodes[leaf];
+            node.tight_minimums = minimums;
+            node.tight_maximums = maximums;
+            if node.minimums.cmple(minimums).all() && maximums.cmple(node.maximums).all() {
+                return;
+            }
+
+            self.remove_leaf(leaf);
+            let node = &mut self.nodes[leaf];
+            node.minimums = minimums - Vector::splat(AABB_MARGIN);
+            node.maximums = maximums + Vector::splat(AABB_MARGIN);
+            self.insert_leaf(leaf);
+        } else {
+            let leaf = self.allocate_node();
+            self.nodes[leaf] = Node {
+                minimums: minimums - Vector::splat(AABB_MARGIN),
+                maximums: maximums + Vector::splat(AABB_MARGIN),
+                tight_minimums: minimums,
+                tight_maximums: maximums,
+                height: 0,
+                entity: Some(entity),
+                ..Default::default()
+            };
+            self.leaves.insert(entity, leaf);
+            self.insert_leaf(leaf);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(leaf) = self.leaves.remove(&entity) {
+            self.remove_leaf(leaf);
+            self.free_node(leaf);
+        }
+    }
+
+    /// Removes every entity keep returns false for
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+        let mut stale = self
+            .leaves
+            .keys()
+            .copied()
+            .filter(|&entity| !keep(entity))
+            .collect::<Vec<_>>();
+        // Map order changes between runs, removal order decides which nodes get reused
+        stale.sort_unstable();
+        for entity in stale {
+            self.remove(entity);
+        }
+    }
+
+    /// Calls back with every entity whose bounds overlap the box
+    pub fn query_aabb(&self, minimums: Vector, maximums: Vector, mut callback: impl FnMut(Entity)) {
+        self.query_nodes(minimums, maximums, |node| {
+            if overlaps(node.tight_minimums, node.tight_maximums, minimums, maximums) {
+                callback(node.entity.unwrap());
+            }
+        });
+    }
+
+    pub fn query_point(&self, point: Vector, callback: impl FnMut(Entity)) {
+        self.query_aabb(point, point, callback);
+    }
+
+    /// Calls back once for every pair of entities with overlapping bounds, in the same order
+    /// every run for the same inserts and removes
+    pub fn for_each_pair(&self, mut callback: impl FnMut(Entity, Entity)) {
+        // Walks the node array rather than the leaf map, its order is stable
+        for node in self.nodes.iter().filter(|node| node.height == 0) {
+            let entity = node.entity.unwrap();
+            self.query_nodes(node.tight_minimums, node.tight_maximums, |other| {
+                let other_entity = other.entity.unwrap();
+                // Each pair is found from both sides, only keep one of them
+                if other_entity <= entity {
+                    return;
+                }
+                // Same strict test as the sweep, touching boxes dont count
+                if node.tight_minimums.cmplt(other.tight_maximums).all()
+                    && other.tight_minimums.cmplt(node.tight_maximums).all()
+                {
+                    callback(entity, other_entity);
+                }
+            });
+        }
+    }
+
+    // Visits every leaf whose fat bounds overlap the box
+    fn query_nodes(&self, minimums: Vector, maximums: Vector, mut callback: impl FnMut(&Node)) {
+        if self.root == NULL_NODE {
+            return;
+        }
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(node.minimums, node.maximums, minimums, maximums) {
+                continue;
+            }
+            if node.is_leaf() {
+                callback(node);
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+    }
+
+    fn allocate_node(&mut self) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Node::default();
+                index
+            }
+            None => {
+                self.nodes.push(Node::default());
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn free_node(&mut self, index: usize) {
+        self.nodes[index].height = -1;
+        self.nodes[index].entity = None;
+        self.free.push(index);
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL_NODE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL_NODE;
+            return;
+        }
+
+        // Find the best sibling, going down the tree while it's cheaper than stopping here
+        let leaf_min = self.nodes[leaf].minimums;
+        let leaf_max = self.nodes[leaf].maximums;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = self.nodes[index];
+            let area = surface_area(node.minimums, node.maximums);
+            let combined_area =
+                surface_area(node.minimums.min(leaf_min), node.maximums.max(leaf_max));
+
+            // Cost of creating a new parent for this node and the new leaf
+            let cost = 2.0 * combined_area;
+            // Minimum cost of pushing the leaf further down the tree
+            let inheritance_cost = 2.0 * (combined_area - area);
+
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let area = surface_area(child.minimums.min(leaf_min), child.maximums.max(leaf_max));
+                if child.is_leaf() {
+                    area + inheritance_cost
+                } else {
+                    area - surface_area(child.minimums, child.maximums) + inheritance_cost
+                }
+            };
+            let cost_left = child_cost(node.left);
+            let cost_right = child_cost(node.right);
+
+            if cost < cost_left && cost < cost_right {
+                break;
+            }
+            index = if cost_left < cost_right {
+                node.left
+            } else {
+                node.right
+            };
+        }
+        let sibling = index;
+
+        // Create a new parent for the sibling and the leaf
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate_node();
+        self.nodes[new_parent] = Node {
+            minimums: self.nodes[sibling].minimums.min(leaf_min),
+            maximums: self.nodes[sibling].maximums.max(leaf_max),
+            parent: old_parent,
+            left: sibling,
+            right: leaf,
+            height: self.nodes[sibling].height + 1,
+            ..Default::default()
+        };
+        self.replace_child(old_parent, sibling, new_parent);
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+
+        // Walk back up fixing heights and bounds
+        self.refit_from(new_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL_NODE;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grand_parent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf {
+            self.nodes[parent].right
+        } else {
+            self.nodes[parent].left
+        };
+
+        // The sibling takes the parents place
+        self.replace_child(grand_parent, parent, sibling);
+        self.nodes[sibling].parent = grand_parent;
+        self.free_node(parent);
+        if grand_parent != NULL_NODE {
+            self.refit_from(grand_parent);
+        }
+    }
+
+    // Points parent at new_child instead of old_child, parent may be null for the root
+    fn replace_child(&mut self, parent: usize, old_child: usize, new_child: usize) {
+        if parent == NULL_NODE {
+            self.root = new_child;
+        } else if self.nodes[parent].left == old_child {
+            self.nodes[parent].left = new_child;
+        } else {
+            self.nodes[parent].right = new_child;
+        }
+    }
+
+    fn refit_from(&mut self, index: usize) {
+        let mut index = index;
+        while index != NULL_NODE {
+            index = self.balance(index);
+            self.refit(index);
+            index = self.nodes[index].parent;
+        }
+    }
+
+    fn refit(&mut self, index: usize) {
+        let left = self.nodes[self.nodes[index].left];
+        let right = self.nodes[self.nodes[index].right];
+        let node = &mut self.nodes[index];
+        node.minimums = left.minimums.min(right.minimums);
+        node.maximums = left.maximums.max(right.maximums);
+        node.height = 1 + left.height.max(right.height);
+    }
+
+    // Rotates a's taller child up if a is unbalanced, returns whatever now sits where a was
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a;
+        }
+
+        let b = self.nodes[a].left;
+        let c = self.nodes[a].right;
+        let balance = self.nodes[c].height - self.nodes[b].height;
+
+        if balance > 1 {
+            self.rotate_up(a, c, b, false)
+        } else if balance < -1 {
+            self.rotate_up(a, b, c, true)
+        } else {
+            a
+        }
+    }
+
+    // Moves child up into a's place, a takes over one of childs children.
+    // other is a's remaining child, child_is_left tells which side child was on
+    fn rotate_up(&mut self, a: usize, child: usize, other: usize, child_is_left: bool) -> usize {
+        let f = self.nodes[child].left;
+        let g = self.nodes[child].right;
+
+        // Swap a and child
+        let a_parent = self.nodes[a].parent;
+        self.nodes[child].left = a;
+        self.nodes[child].parent = a_parent;
+        self.nodes[a].parent = child;
+        self.replace_child(a_parent, a, child);
+
+        // Keep the taller grand child on child, give the other to a
+        let (keep, give) = if self.nodes[f].height > self.nodes[g].height {
+            (f, g)
+        } else {
+            (g, f)
+        };
+        self.nodes[child].right = keep;
+        if child_is_left {
+            self.nodes[a].left = give;
+        } else {
+            self.nodes[a].right = give;
+        }
+        self.nodes[give].parent = a;
+        debug_assert!(self.nodes[a].left == other || self.nodes[a].right == other);
+
+        self.refit(a);
+        self.refit(child);
+        child
+    }
+}
+
+fn overlaps(min_a: Vector, max_a: Vector, min_b: Vector, max_b: Vector) -> bool {
+    min_a.cmple(max_b).all() && min_b.cmple(max_a).all()
+}
+
+fn surface_area(minimums: Vector, maximums: Vector) -> Real {
+    let d = maximums - minimums;
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        bvh_broadphase_system,
+        test_util::{broad_pairs, brute_force_pairs, run, spawn_bounds, test_world, Rng},
+        BroadphaseMode, GlobalAabb, PhysicsConfig, Real, Vector,
+    };
+
+    use super::Bvh;
+
+    fn found(bvh: &Bvh, point: Vector) -> Vec<Entity> {
+        let mut found = Vec::new();
+        bvh.query_point(point, |entity| found.push(entity));
+        found
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut world = World::new();
+        let (a, b) = (world.spawn().id(), world.spawn().id());
+        let mut bvh = Bvh::default();
+        bvh.set(a, -Vector::ONE, Vector::ONE);
+        bvh.set(b, Vector::splat(2.0), Vector::splat(4.0));
+        assert_eq!(bvh.len(), 2);
+        assert_eq!(found(&bvh, Vector::ZERO), vec![a]);
+        assert_eq!(found(&bvh, Vector::splat(3.0)), vec![b]);
+
+        bvh.remove(a);
+        assert_eq!(bvh.len(), 1);
+        assert!(found(&bvh, Vector::ZERO).is_empty());
+        bvh.retain(|entity| entity != b);
+        assert!(bvh.is_empty());
+        assert_eq!(bvh.height(), 0);
+    }
+
+    #[test]
+    fn moved_leaf_is_found_where_it_is() {
+        let mut world = World::new();
+        let entity = world.spawn().id();
+        let mut bvh = Bvh::default();
+        bvh.set(entity, -Vector::ONE, Vector::ONE);
+
+        // Inside the margin the tree is left alone, queries still use the real bounds
+        bvh.set(
+            entity,
+            Vector::new(-0.95, -1.0, -1.0),
+            Vector::new(1.05, 1.0, 1.0),
+        );
+        assert!(found(&bvh, Vector::new(-0.98, 0.0, 0.0)).is_empty());
+        assert_eq!(found(&bvh, Vector::new(1.02, 0.0, 0.0)), vec![entity]);
+
+        // Past the margin the leaf is reinserted
+        bvh.set(
+            entity,
+            Vector::new(4.0, -1.0, -1.0),
+            Vector::new(6.0, 1.0, 1.0),
+        );
+        assert!(found(&bvh, Vector::ZERO).is_empty());
+        assert_eq!(found(&bvh, Vector::new(5.0, 0.0, 0.0)), vec![entity]);
+        assert_eq!(bvh.len(), 1);
+    }
+
+    #[test]
+    fn height_stays_balanced() {
+        let mut world = World::new();
+        let mut bvh = Bvh::default();
+        // Inserting along a line is the worst case for an unbalanced tree
+        let count = 1024;
+        for i in 0..count {
+            let center = Vector::X * (i as Real * 3.0);
+            bvh.set(
+                world.spawn().id(),
+                center - Vector::ONE,
+                center + Vector::ONE,
+            );
+        }
+        assert_eq!(bvh.len(), count);
+        // log2 of the leaf count is 10
+        assert!(bvh.height() <= 20, "height {}", bvh.height());
+    }
+
+    // Runs the broad phase, checks its pairs and returns how many there were
+    fn frame(world: &mut World) -> usize {
+        run(world, bvh_broadphase_system);
+        let pairs = broad_pairs(world);
+        assert_eq!(pairs, brute_force_pairs(world));
+        pairs.len()
+    }
+
+    #[test]
+    fn pairs_match_brute_force() {
+        let mut world = test_world();
+        world
+            .get_resource_mut::<PhysicsConfig>()
+            .unwrap()
+            .broadphase = BroadphaseMode::Bvh;
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut entities = (0..80)
+            .map(|_| {
+                let center = rng.vector(Vector::ZERO, Vector::splat(20.0));
+                let half_extents = rng.vector(Vector::splat(0.5), Vector::splat(2.0));
+                spawn_bounds(&mut world, center, half_extents)
+            })
+            .collect::<Vec<_>>();
+
+        assert!(frame(&mut world) > 0);
+
+        // Some moves stay inside the margin, others go well past it
+        for step in [0.05, 3.0] {
+            for &entity in entities.iter() {
+                let offset = rng.vector(Vector::splat(-step), Vector::splat(step));
+                let mut aabb = world.get_mut::<GlobalAabb>(entity).unwrap();
+                aabb.minimums += offset;
+                aabb.maximums += offset;
+            }
+            frame(&mut world);
+        }
+
+        for entity in entities.drain(..10) {
+            world.despawn(entity);
+        }
+        frame(&mut world);
+    }
+}
@@ -0,0 +1,385 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{aabb_aabb_intersect, Real, Vector};
+
+// Anything covering more cells than this is tested against everything instead
+const MAX_CELLS_PER_ENTRY: i64 = 64;
+
+// Cell coordinates. i64, so bodies far from the origin with the f64 feature
+// still get cells of their own instead of all landing in the last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Cell {
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GridEntry {
+    entity: Entity,
+    minimums: Vector,
+    maximums: Vector,
+    // Too big to put in cells, tested against everything instead
+    oversized: bool,
+}
+
+/// Uniform grid keyed by cell coordinates, rebuilt every frame. Best when bodies are
+/// many and about the same size, like thousands of equal balls
+#[derive(Default)]
+pub struct SpatialHashGrid {
+    cell_size: Real,
+    entries: Vec<GridEntry>,
+    cells: HashMap<Cell, Vec<usize>>,
+    // Entries too big to put in cells
+    oversized: Vec<usize>,
+}
+
+impl SpatialHashGrid {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn cell_size(&self) -> Real {
+        self.cell_size
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.cells.clear();
+        self.oversized.clear();
+    }
+
+    /// Rebuilds the grid, with a cell_size of 0 or less the median body size is used.
+    /// Bounds have to be valid, see [GlobalAabb::is_valid](crate::GlobalAabb::is_valid)
+    pub fn rebuild(
+        &mut self,
+        cell_size: Real,
+        bodies: impl Iterator<Item = (Entity, Vector, Vector)>,
+    ) {
+        self.entries.clear();
+        self.oversized.clear();
+        // Keep the allocations of cells still in use, drop the rest
+        self.cells.retain(|_, cell| {
+            let keep = !cell.is_empty();
+            cell.clear();
+            keep
+        });
+
+        self.entries
+            .extend(bodies.map(|(entity, minimums, maximums)| GridEntry {
+                entity,
+                minimums,
+                maximums,
+                oversized: false,
+            }));
+
+        self.cell_size = if cell_size > 0.0 {
+            cell_size
+        } else {
+            self.median_size()
+        };
+
+        let cell_size = self.cell_size;
+        let cells = &mut self.cells;
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            let min_cell = cell_of(entry.minimums, cell_size);
+            let max_cell = cell_of(entry.maximums, cell_size);
+            if cell_count(min_cell, max_cell) > MAX_CELLS_PER_ENTRY {
+                entry.oversized = true;
+                self.oversized.push(index);
+                continue;
+            }
+            for x in min_cell.x..=max_cell.x {
+                for y in min_cell.y..=max_cell.y {
+                    for z in min_cell.z..=max_cell.z {
+                        cells.entry(Cell { x, y, z }).or_default().push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls back once for every pair of entities with overlapping bounds
+    pub fn for_each_pair(&self, mut callback: impl FnMut(Entity, Entity)) {
+        for (cell, indices) in self.cells.iter() {
+            for (i, &index_a) in indices.iter().enumerate() {
+                let a = &self.entries[index_a];
+                for &index_b in indices.iter().skip(i + 1) {
+                    let b = &self.entries[index_b];
+                    // Same strict test as the sweep, touching boxes dont count
+                    if !(a.minimums.cmplt(b.maximums).all() && b.minimums.cmplt(a.maximums).all()) {
+                        continue;
+                    }
+                    // Pairs sharing several cells are only reported from the one holding the
+                    // corner of their overlap
+                    if cell_of(a.minimums.max(b.minimums), self.cell_size) != *cell {
+                        continue;
+                    }
+                    callback(a.entity, b.entity);
+                }
+            }
+        }
+
+        // Oversized entries are tested against everything
+        for &index_a in self.oversized.iter() {
+            let a = &self.entries[index_a];
+            for (index_b, b) in self.entries.iter().enumerate() {
+                // Pairs of oversized entries are found from both sides, only keep one
+                if b.oversized && index_b <= index_a {
+                    continue;
+                }
+                if a.minimums.cmplt(b.maximums).all() && b.minimums.cmplt(a.maximums).all() {
+                    callback(a.entity, b.entity);
+                }
+            }
+        }
+    }
+
+    /// Calls back with every entity whose bounds overlap the box
+    pub fn query_aabb(&self, minimums: Vector, maximums: Vector, mut callback: impl FnMut(Entity)) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let min_cell = cell_of(minimums, self.cell_size);
+        let max_cell = cell_of(maximums, self.cell_size);
+        if cell_count(min_cell, max_cell) > self.cells.len() as i64 {
+            // Cheaper to look at every entry than every cell the box covers
+            let mut seen = HashSet::default();
+            for indices in self.cells.values() {
+                for &index in indices {
+                    let entry = &self.entries[index];
+                    if seen.insert(index)
+                        && aabb_aabb_intersect(entry.minimums, entry.maximums, minimums, maximums)
+                    {
+                        callback(entry.entity);
+                    }
+                }
+            }
+        } else {
+            for x in min_cell.x..=max_cell.x {
+                for y in min_cell.y..=max_cell.y {
+                    for z in min_cell.z..=max_cell.z {
+                        let cell = Cell { x, y, z };
+                        let indices = match self.cells.get(&cell) {
+                            Some(indices) => indices,
+                            None => continue,
+                        };
+                        for &index in indices {
+                            let entry = &self.entries[index];
+                            if !aabb_aabb_intersect(
+                                entry.minimums,
+                                entry.maximums,
+                                minimums,
+                                maximums,
+                            ) {
+                                continue;
+                            }
+                            // Only report from the cell holding the corner of the overlap
+                            if cell_of(entry.minimums.max(minimums), self.cell_size) == cell {
+                                callback(entry.entity);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for &index in self.oversized.iter() {
+            let entry = &self.entries[index];
+            if aabb_aabb_intersect(entry.minimums, entry.maximums, minimums, maximums) {
+                callback(entry.entity);
+            }
+        }
+    }
+
+    pub fn query_point(&self, point: Vector, callback: impl FnMut(Entity)) {
+        self.query_aabb(point, point, callback);
+    }
+
+    fn median_size(&self) -> Real {
+        if self.entries.is_empty() {
+            return 1.0;
+        }
+        let mut sizes = self
+            .entries
+            .iter()
+            .map(|entry| (entry.maximums - entry.minimums).max_element())
+            .collect::<Vec<_>>();
+        let middle = sizes.len() / 2;
+        let (_, median, _) = sizes.select_nth_unstable_by(middle, |a, b| {
+            a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        if *median > 0.0 {
+            *median
+        } else {
+            1.0
+        }
+    }
+}
+
+fn cell_of(point: Vector, cell_size: Real) -> Cell {
+    let cell = (point / cell_size).floor();
+    Cell {
+        x: cell.x as i64,
+        y: cell.y as i64,
+        z: cell.z as i64,
+    }
+}
+
+// Saturates, a large box over small cells easily overflows
+fn cell_count(min_cell: Cell, max_cell: Cell) -> i64 {
+    let cells = |min: i64, max: i64| max.saturating_sub(min).saturating_add(1);
+    cells(min_cell.x, max_cell.x)
+        .saturating_mul(cells(min_cell.y, max_cell.y))
+        .saturating_mul(cells(min_cell.z, max_cell.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        grid_broadphase_system,
+        test_util::{broad_pairs, brute_force_pairs, run, spawn_bounds, test_world, Rng},
+        BroadphaseMode, PhysicsConfig, Vector,
+    };
+
+    use super::SpatialHashGrid;
+
+    fn pairs(grid: &SpatialHashGrid) -> Vec<(Entity, Entity)> {
+        let mut pairs = Vec::new();
+        grid.for_each_pair(|a, b| pairs.push((a.min(b), a.max(b))));
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn pair_sharing_cells_reported_once() {
+        let mut world = World::new();
+        let (a, b, c) = (world.spawn().id(), world.spawn().id(), world.spawn().id());
+        let mut grid = SpatialHashGrid::default();
+        grid.rebuild(
+            1.0,
+            [
+                // Overlap covers eight cells
+                (a, Vector::splat(0.5), Vector::splat(2.5)),
+                (b, Vector::splat(1.2), Vector::splat(3.2)),
+                // Only touches b
+                (c, Vector::splat(3.2), Vector::splat(4.0)),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(pairs(&grid), vec![(a.min(b), a.max(b))]);
+    }
+
+    #[test]
+    fn far_cells_stay_apart() {
+        let mut world = World::new();
+        let (a, b) = (world.spawn().id(), world.spawn().id());
+        let mut grid = SpatialHashGrid::default();
+        // Past what an i32 cell coordinate holds
+        let far = Vector::X * 3.0e9;
+        let size = Vector::new(0.0, 0.5, 0.5);
+        let apart = Vector::X * 1024.0;
+        grid.rebuild(
+            1.0,
+            [(a, far, far + size), (b, far + apart, far + apart + size)].into_iter(),
+        );
+        assert_eq!(grid.cells.len(), 2);
+    }
+
+    #[test]
+    fn oversized_entries_tested_against_everything() {
+        let mut world = World::new();
+        let entities = (0..4).map(|_| world.spawn().id()).collect::<Vec<_>>();
+        let (a, b, c, d) = (entities[0], entities[1], entities[2], entities[3]);
+        let mut grid = SpatialHashGrid::default();
+        grid.rebuild(
+            1.0,
+            [
+                (a, Vector::splat(-10.0), Vector::splat(10.0)),
+                (b, Vector::splat(5.0), Vector::splat(15.0)),
+                (c, Vector::splat(-0.4), Vector::splat(0.4)),
+                (d, Vector::splat(29.6), Vector::splat(30.4)),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(grid.oversized.len(), 2);
+
+        let mut expected = vec![(a.min(b), a.max(b)), (a.min(c), a.max(c))];
+        expected.sort_unstable();
+        assert_eq!(pairs(&grid), expected);
+
+        let mut found = Vec::new();
+        grid.query_point(Vector::ZERO, |entity| found.push(entity));
+        found.sort_unstable();
+        let mut expected = vec![a, c];
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn cell_size_defaults_to_median() {
+        let mut world = World::new();
+        let mut grid = SpatialHashGrid::default();
+        let bodies = [1.0, 4.0, 3.0, 10.0, 2.0]
+            .iter()
+            .map(|&size| {
+                (
+                    world.spawn().id(),
+                    Vector::ZERO,
+                    Vector::new(size, 0.5, 0.5),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        grid.rebuild(0.0, bodies.iter().copied());
+        assert_eq!(grid.cell_size(), 3.0);
+        grid.rebuild(2.5, bodies.iter().copied());
+        assert_eq!(grid.cell_size(), 2.5);
+        grid.rebuild(0.0, std::iter::empty());
+        assert_eq!(grid.cell_size(), 1.0);
+    }
+
+    #[test]
+    fn pairs_match_brute_force() {
+        let mut world = test_world();
+        {
+            let mut config = world.get_resource_mut::<PhysicsConfig>().unwrap();
+            config.broadphase = BroadphaseMode::SpatialHash;
+            config.grid_cell_size = 0.0;
+        }
+        let mut rng = Rng(0x853c_49e6_748f_ea9b);
+        for _ in 0..100 {
+            let center = rng.vector(Vector::ZERO, Vector::splat(20.0));
+            let half_extents = rng.vector(Vector::splat(0.5), Vector::splat(1.5));
+            spawn_bounds(&mut world, center, half_extents);
+        }
+        // Big enough to end up oversized
+        for _ in 0..3 {
+            let center = rng.vector(Vector::ZERO, Vector::splat(20.0));
+            spawn_bounds(&mut world, center, Vector::splat(8.0));
+        }
+
+        run(&mut world, grid_broadphase_system);
+        let pairs = broad_pairs(&mut world);
+        assert!(!pairs.is_empty());
+        assert_eq!(pairs, brute_force_pairs(&mut world));
+        assert_eq!(
+            world
+                .get_resource::<SpatialHashGrid>()
+                .unwrap()
+                .oversized
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,7 @@
+mod bvh;
+mod grid;
+mod sap;
+
+pub use bvh::*;
+pub use grid::*;
+pub use sap::*;
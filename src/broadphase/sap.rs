@@ -0,0 +1,267 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{aabb_aabb_intersect, GlobalAabb, Real, Vector};
+
+// A new axis has to spread things this much more before we pay for a full re-sort
+const AXIS_SWITCH_RATIO: Real = 1.2;
+
+/// Bounds of each collider sorted along the sweep axis. Kept between frames since its already
+/// nearly sorted, and so scene queries can reuse it
+#[derive(Default)]
+pub struct SweepAndPrune {
+    pub(crate) entries: Vec<SapEntry>,
+    pub(crate) members: HashSet<Entity>,
+    // Axis with the greatest spread of centers, 0 = x, 1 = y, 2 = z
+    pub(crate) axis: usize,
+    // Largest size along the sweep axis, tells queries how far back an overlapping entry can start
+    pub(crate) max_extent: Real,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SapEntry {
+    pub entity: Entity,
+    pub minimums: Vector,
+    pub maximums: Vector,
+}
+
+impl SweepAndPrune {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Axis the list is currently sorted on, 0 = x, 1 = y, 2 = z
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    /// Calls back with every entity whose bounds overlap the box
+    pub fn query_aabb(&self, minimums: Vector, maximums: Vector, mut callback: impl FnMut(Entity)) {
+        // Nothing starting before this can reach the box
+        let axis = self.axis;
+        let first = self
+            .entries
+            .partition_point(|entry| entry.minimums[axis] < minimums[axis] - self.max_extent);
+        for entry in self.entries[first..].iter() {
+            if entry.minimums[axis] > maximums[axis] {
+                break;
+            }
+            if aabb_aabb_intersect(entry.minimums, entry.maximums, minimums, maximums) {
+                callback(entry.entity);
+            }
+        }
+    }
+
+    pub fn query_point(&self, point: Vector, callback: impl FnMut(Entity)) {
+        self.query_aabb(point, point, callback);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.members.clear();
+        self.max_extent = 0.0;
+    }
+
+    /// Appends an entity, returns false if it was already tracked
+    pub(crate) fn insert(&mut self, entity: Entity) -> bool {
+        if !self.members.insert(entity) {
+            return false;
+        }
+        self.entries.push(SapEntry {
+            entity,
+            minimums: Vector::ZERO,
+            maximums: Vector::ZERO,
+        });
+        true
+    }
+
+    /// Refreshes every entries bounds, dropping the ones bounds returns None for.
+    /// Returns true if the sweep axis changed and the list needs a full sort
+    pub(crate) fn refresh(&mut self, mut bounds: impl FnMut(Entity) -> Option<GlobalAabb>) -> bool {
+        let members = &mut self.members;
+        let mut max_extents = Vector::ZERO;
+        let mut sum = Vector::ZERO;
+        let mut sum_sq = Vector::ZERO;
+        let mut count = 0;
+
+        // retain keeps the order, so the list stays nearly sorted
+        self.entries.retain_mut(|entry| match bounds(entry.entity) {
+            Some(aabb) => {
+                entry.minimums = aabb.minimums;
+                entry.maximums = aabb.maximums;
+                // Keep bounds update_aabb hasnt set yet out of the stats
+                if aabb.is_valid() {
+                    max_extents = max_extents.max(aabb.maximums - aabb.minimums);
+                    let center = 0.5 * (aabb.minimums + aabb.maximums);
+                    sum += center;
+                    sum_sq += center * center;
+                    count += 1;
+                }
+                true
+            }
+            None => {
+                members.remove(&entry.entity);
+                false
+            }
+        });
+
+        // Sweep along the axis the centers are most spread out on, so the sweep can stop early
+        let old_axis = self.axis;
+        if count > 0 {
+            let n = count as Real;
+            let mean = sum / n;
+            let variance = sum_sq / n - mean * mean;
+            let mut axis = old_axis;
+            for i in 0..3 {
+                if variance[i] > variance[axis] * AXIS_SWITCH_RATIO {
+                    axis = i;
+                }
+            }
+            self.axis = axis;
+        }
+        self.max_extent = max_extents[self.axis];
+        self.axis != old_axis
+    }
+
+    /// Insertion sort, close to linear since things dont move far between frames
+    pub(crate) fn sort(&mut self) {
+        let axis = self.axis;
+        let entries = &mut self.entries;
+        for i in 1..entries.len() {
+            let mut j = i;
+            while j > 0 && entries[j - 1].minimums[axis] > entries[j].minimums[axis] {
+                entries.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
+    /// Full sort, for when the list is far from sorted
+    pub(crate) fn sort_full(&mut self) {
+        let axis = self.axis;
+        self.entries.sort_unstable_by(|a, b| {
+            a.minimums[axis]
+                .partial_cmp(&b.minimums[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        broadphase_system,
+        test_util::{broad_pairs, brute_force_pairs, spawn_bounds, test_world, Rng},
+        GlobalAabb, Real, SweepAndPrune, Vector,
+    };
+
+    // Scatters boxes inside the region, sized so a fair number of them overlap
+    fn scatter(world: &mut World, rng: &mut Rng, count: usize, region: Vector) -> Vec<Entity> {
+        (0..count)
+            .map(|_| {
+                let center = rng.vector(Vector::ZERO, region);
+                let half_extents = rng.vector(Vector::splat(0.5), Vector::splat(1.5));
+                spawn_bounds(world, center, half_extents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pairs_match_brute_force_across_frames() {
+        let mut world = test_world();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(broadphase_system);
+        let mut frame = |world: &mut World| {
+            stage.run(world);
+            let pairs = broad_pairs(world);
+            assert_eq!(pairs, brute_force_pairs(world));
+            pairs.len()
+        };
+
+        let mut entities = scatter(&mut world, &mut rng, 60, Vector::new(40.0, 4.0, 4.0));
+        assert!(frame(&mut world) > 0);
+        assert_eq!(world.get_resource::<SweepAndPrune>().unwrap().axis(), 0);
+
+        // Small moves keep the list nearly sorted, the insertion sort fixes it up
+        for _ in 0..5 {
+            for &entity in entities.iter() {
+                let offset = rng.vector(Vector::splat(-0.5), Vector::splat(0.5));
+                let mut aabb = world.get_mut::<GlobalAabb>(entity).unwrap();
+                aabb.minimums += offset;
+                aabb.maximums += offset;
+            }
+            frame(&mut world);
+        }
+
+        // A few new entries are sorted in, more than an eighth re-sorts the whole list
+        entities.extend(scatter(
+            &mut world,
+            &mut rng,
+            3,
+            Vector::new(40.0, 4.0, 4.0),
+        ));
+        frame(&mut world);
+        entities.extend(scatter(
+            &mut world,
+            &mut rng,
+            20,
+            Vector::new(40.0, 4.0, 4.0),
+        ));
+        frame(&mut world);
+
+        for entity in entities.drain(..5) {
+            world.despawn(entity);
+        }
+        frame(&mut world);
+        assert_eq!(world.get_resource::<SweepAndPrune>().unwrap().len(), 78);
+
+        // Stack everything up along y, the sweep has to follow
+        for &entity in entities.iter() {
+            let center = rng.vector(Vector::ZERO, Vector::new(4.0, 40.0, 4.0));
+            let mut aabb = world.get_mut::<GlobalAabb>(entity).unwrap();
+            let half_extents = 0.5 * (aabb.maximums - aabb.minimums);
+            aabb.minimums = center - half_extents;
+            aabb.maximums = center + half_extents;
+        }
+        assert!(frame(&mut world) > 0);
+        assert_eq!(world.get_resource::<SweepAndPrune>().unwrap().axis(), 1);
+    }
+
+    #[test]
+    fn axis_switch_needs_clearly_more_spread() {
+        let mut world = World::new();
+        let entities = (0..10).map(|_| world.spawn().id()).collect::<Vec<_>>();
+        let mut sap = SweepAndPrune::default();
+        for &entity in entities.iter() {
+            sap.insert(entity);
+        }
+        // Centers spaced out along a line, y spread is scale times the x spread
+        let refresh = |sap: &mut SweepAndPrune, scale_x: Real, scale_y: Real| {
+            sap.refresh(|entity| {
+                let i = entities.iter().position(|&e| e == entity).unwrap() as Real;
+                let center = Vector::new(i * scale_x, i * scale_y, 0.0);
+                Some(GlobalAabb {
+                    minimums: center - Vector::splat(0.5),
+                    maximums: center + Vector::splat(0.5),
+                })
+            })
+        };
+
+        // Variance goes with the square, 1.05 gives 1.1 which is under the switch ratio
+        assert!(!refresh(&mut sap, 1.0, 1.05));
+        assert_eq!(sap.axis(), 0);
+        assert!(refresh(&mut sap, 1.0, 1.2));
+        assert_eq!(sap.axis(), 1);
+        // Same margin is needed to switch back
+        assert!(!refresh(&mut sap, 1.05, 1.0));
+        assert_eq!(sap.axis(), 1);
+        assert!(refresh(&mut sap, 1.2, 1.0));
+        assert_eq!(sap.axis(), 0);
+    }
+}
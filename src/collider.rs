@@ -1,7 +1,7 @@
-use bevy::{prelude::Component, math::{Vec3, Mat3}};
+use bevy::prelude::Component;
 use bevy_inspector_egui::Inspectable;
 
-use crate::Aabb;
+use crate::{Aabb, Matrix, Real, Vector};
 
 #[derive(Component, Inspectable)]
 pub enum ColliderType {
@@ -10,14 +10,14 @@ pub enum ColliderType {
 
 pub trait Collider {
     fn get_type(&self) -> ColliderType;
-    fn get_center_of_mass(&self) -> Vec3;
-    fn get_inertia_tensor(&self) -> Mat3;
+    fn get_center_of_mass(&self) -> Vector;
+    fn get_inertia_tensor(&self) -> Matrix;
     fn get_aabb(&self) -> Aabb;
 }
 
 #[derive(Component, Inspectable)]
 pub struct ColliderSphere {
-    pub radius: f32
+    pub radius: Real
 }
 
 impl Collider for ColliderSphere {
@@ -25,25 +25,25 @@ impl Collider for ColliderSphere {
         ColliderType::Sphere
     }
 
-    fn get_center_of_mass(&self) -> Vec3 {
-        Vec3::ZERO
+    fn get_center_of_mass(&self) -> Vector {
+        Vector::ZERO
     }
 
-    fn get_inertia_tensor(&self) -> Mat3 {
+    fn get_inertia_tensor(&self) -> Matrix {
         let i = 2.0 * self.radius * self.radius / 5.0;
-        Mat3::from_diagonal(Vec3::splat(i) )
+        Matrix::from_diagonal(Vector::splat(i) )
     }
 
     fn get_aabb(&self) -> Aabb {
         Aabb {
-            minimums: Vec3::new(-self.radius, -self.radius, -self.radius),
-            maximums: Vec3::new(self.radius, self.radius, self.radius),
+            minimums: Vector::new(-self.radius, -self.radius, -self.radius),
+            maximums: Vector::new(self.radius, self.radius, self.radius),
         }
     }
 }
 
 impl ColliderSphere {
-    pub fn new(radius: f32) -> Self {
+    pub fn new(radius: Real) -> Self {
         ColliderSphere {
             radius
         }
@@ -1,4 +1,10 @@
-use bevy::{prelude::Entity, math::Vec3};
+use bevy::{
+    prelude::{Component, Entity},
+    utils::HashSet,
+};
+use bevy_inspector_egui::Inspectable;
+
+use crate::{Real, Vector};
 
 #[derive(Debug)]
 pub struct BroadContact {
@@ -10,11 +16,105 @@ pub struct BroadContact {
 pub struct Contact {
     pub a: Entity,
     pub b: Entity,
-    pub world_point_a: Vec3,
-    pub world_point_b: Vec3,
-    pub local_point_a: Vec3,
-    pub local_point_b: Vec3,
-    pub normal: Vec3,
-    pub separation_dist: f32,
-    pub time_of_impact: f32,
-}
\ No newline at end of file
+    pub world_point_a: Vector,
+    pub world_point_b: Vector,
+    pub local_point_a: Vector,
+    pub local_point_b: Vector,
+    /// Points from a to b, the solver pushes b along it and a against it
+    pub normal: Vector,
+    pub separation_dist: Real,
+    pub time_of_impact: Real,
+
+    // combined from both bodies, can be changed by a ContactModifier
+    pub elasticity: Real,
+    pub friction: Real,
+    /// Tangential velocity of b's surface, for conveyor belts and the like
+    pub surface_velocity: Vector,
+}
+
+/// Impulse magnitudes applied while resolving a contact, useful for impact sounds and damage
+#[derive(Debug)]
+pub struct ContactImpulse {
+    pub a: Entity,
+    pub b: Entity,
+    pub world_point: Vector,
+    pub normal: Vector,
+    pub normal_impulse: Real,
+    pub friction_impulse: Real,
+}
+
+impl ContactImpulse {
+    pub fn total(&self) -> Real {
+        self.normal_impulse + self.friction_impulse
+    }
+}
+
+/// Only report [ContactImpulse] for this body when the total impulse is at least this large.
+/// Applies to every contact the body is in, even with bodies that have no threshold.
+/// If both have one the lower threshold wins
+#[derive(Component, Inspectable, Default, Debug)]
+pub struct ContactImpulseThreshold(pub Real);
+
+/// Sent the first frame two bodies are found touching
+#[derive(Debug)]
+pub struct CollisionStarted(pub Entity, pub Entity);
+
+/// Sent the first frame two previously touching bodies are no longer found touching
+#[derive(Debug)]
+pub struct CollisionEnded(pub Entity, pub Entity);
+
+/// Every entity a body is currently touching, kept in sync with [CollisionStarted] and [CollisionEnded]
+#[derive(Component, Default, Debug)]
+pub struct CollidingEntities {
+    entities: HashSet<Entity>,
+}
+
+impl CollidingEntities {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, entity: Entity) {
+        self.entities.insert(entity);
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) {
+        self.entities.remove(&entity);
+    }
+}
+
+/// Pairs that were touching last frame, always stored with the lower entity first
+#[derive(Default)]
+pub struct CollisionPairs {
+    pub(crate) active: HashSet<(Entity, Entity)>,
+}
+
+impl CollisionPairs {
+    pub fn contains(&self, a: Entity, b: Entity) -> bool {
+        self.active.contains(&ordered_pair(a, b))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.active.iter().copied()
+    }
+}
+
+pub(crate) fn ordered_pair(a: Entity, b: Entity) -> (Entity, Entity) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
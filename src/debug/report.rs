@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
-use crate::{PhysicsTime, Body, BroadContact, Contact};
+use crate::{from_real, PhysicsTime, Body, BroadContact, Contact, Joint};
 
 
 // TODO: Make this disable so user knows they can't change anything
@@ -21,14 +21,15 @@ pub fn report_system(
     bodies: Query<(&Body, &Transform)>,
     mut collision_pairs: EventReader<BroadContact>,
     mut contacts: EventReader<Contact>,
+    joints: Query<&Joint>,
     //manifolds: Query<&Manifold>,
     //constraint_penetrations: Query<&ConstraintPenetration>,
     mut report: ResMut<PhysicsReport>,
 ) {
-    report.time = pt.time;
+    report.time = from_real(pt.time);
     report.bodies = bodies.iter().count();
     //report.manifolds = manifolds.iter().count();
     report.broad_contacts = collision_pairs.iter().count();
     report.narrow_contacts = contacts.iter().count();
-    //report.constraint = constraint_penetrations.iter().count();
+    report.constraint = joints.iter().count();
 }
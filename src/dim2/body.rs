@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::Inspectable;
+
+use crate::Mass;
+
+/// Body that lives on the XY plane, rotating only around Z
+#[derive(Component, Inspectable, Clone)]
+pub struct Body2d {
+    pub linear_velocity: Vec2,
+    /// Radians a second around Z
+    pub angular_velocity: f32,
+
+    #[inspectable(min = 0.0, max = 1.0)]
+    pub elasticity: f32,
+    pub friction: f32,
+    pub mass: Mass,
+
+    // will be set by collider
+    pub inv_mass: f32,
+    pub inv_inertia: f32,
+}
+
+impl Default for Body2d {
+    fn default() -> Self {
+        Body2d {
+            linear_velocity: Vec2::ZERO,
+            angular_velocity: 0.0,
+            elasticity: 1.0,
+            friction: 0.5,
+            mass: Mass::Static,
+            inv_mass: 0.0,
+            inv_inertia: 0.0,
+        }
+    }
+}
+
+impl Body2d {
+    /// point and impulse are in world space
+    pub fn apply_impulse(&mut self, point: Vec2, position: Vec2, impulse: Vec2) {
+        if self.inv_mass == 0.0 {
+            return;
+        }
+        self.linear_velocity += impulse * self.inv_mass;
+        self.angular_velocity += self.inv_inertia * cross(point - position, impulse);
+    }
+
+    /// Velocity of a world space point attached to the body
+    pub fn velocity_at(&self, point: Vec2, position: Vec2) -> Vec2 {
+        let r = point - position;
+        self.linear_velocity + Vec2::new(-r.y, r.x) * self.angular_velocity
+    }
+}
+
+/// Convex 2D shapes, centered on the entity. Polygon points are put counter clockwise
+/// when the body spawns
+#[derive(Component, Debug, Clone)]
+pub enum Collider2d {
+    Circle { radius: f32 },
+    Box { half_extents: Vec2 },
+    Polygon { points: Vec<Vec2> },
+}
+
+impl Collider2d {
+    /// Moment of inertia around the origin for the given mass
+    pub fn inertia(&self, mass: f32) -> f32 {
+        match self {
+            Collider2d::Circle { radius } => 0.5 * mass * radius * radius,
+            Collider2d::Box { half_extents } => mass * half_extents.length_squared() / 3.0,
+            Collider2d::Polygon { points } => polygon_inertia(points, mass),
+        }
+    }
+
+    /// Radius of a circle around the origin holding the whole shape at any rotation
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            Collider2d::Circle { radius } => *radius,
+            Collider2d::Box { half_extents } => half_extents.length(),
+            Collider2d::Polygon { points } => points.iter().map(|p| p.length()).fold(0.0, f32::max),
+        }
+    }
+
+    /// Reverses clockwise polygon points, edge normals and inertia expect counter clockwise
+    pub fn make_counter_clockwise(&mut self) {
+        if let Collider2d::Polygon { points } = self {
+            if signed_area(points) < 0.0 {
+                points.reverse();
+            }
+        }
+    }
+
+    /// Corners in local space, empty for circles
+    pub fn vertices(&self) -> Vec<Vec2> {
+        match self {
+            Collider2d::Circle { .. } => Vec::new(),
+            Collider2d::Box { half_extents } => vec![
+                Vec2::new(-half_extents.x, -half_extents.y),
+                Vec2::new(half_extents.x, -half_extents.y),
+                Vec2::new(half_extents.x, half_extents.y),
+                Vec2::new(-half_extents.x, half_extents.y),
+            ],
+            Collider2d::Polygon { points } => points.clone(),
+        }
+    }
+}
+
+// Positive when the points go counter clockwise
+fn signed_area(points: &[Vec2]) -> f32 {
+    (0..points.len())
+        .map(|i| 0.5 * cross(points[i], points[(i + 1) % points.len()]))
+        .sum()
+}
+
+// Sum over the triangles fanned out from the origin
+fn polygon_inertia(points: &[Vec2], mass: f32) -> f32 {
+    let mut area = 0.0;
+    let mut inertia = 0.0;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        let triangle_area = 0.5 * cross(a, b);
+        area += triangle_area;
+        inertia += triangle_area * (a.dot(a) + a.dot(b) + b.dot(b)) / 6.0;
+    }
+    if area > 0.0 {
+        mass * inertia / area
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
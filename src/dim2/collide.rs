@@ -0,0 +1,299 @@
+use bevy::prelude::*;
+
+use super::Collider2d;
+
+// How much further apart b's edges have to be before they're used instead of a's
+const REFERENCE_TOLERANCE: f32 = 0.001;
+
+/// Where two 2D shapes touch
+#[derive(Debug, Clone)]
+pub struct Manifold2d {
+    /// Points from a to b
+    pub normal: Vec2,
+    /// One, or two when edges lie on each other
+    pub points: Vec<ManifoldPoint2d>,
+}
+
+impl Manifold2d {
+    fn single(normal: Vec2, point: Vec2, depth: f32) -> Self {
+        Manifold2d {
+            normal,
+            points: vec![ManifoldPoint2d { point, depth }],
+        }
+    }
+
+    /// Depth of the deepest point
+    pub fn depth(&self) -> f32 {
+        self.points.iter().map(|p| p.depth).fold(0.0, f32::max)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ManifoldPoint2d {
+    /// Halfway between the two surfaces, in world space
+    pub point: Vec2,
+    pub depth: f32,
+}
+
+/// Tests two shapes placed at the given positions and angles
+pub fn collide_2d(
+    shape_a: &Collider2d,
+    position_a: Vec2,
+    angle_a: f32,
+    shape_b: &Collider2d,
+    position_b: Vec2,
+    angle_b: f32,
+) -> Option<Manifold2d> {
+    match (shape_a, shape_b) {
+        (Collider2d::Circle { radius: ra }, Collider2d::Circle { radius: rb }) => {
+            circle_circle(position_a, *ra, position_b, *rb)
+        }
+        (Collider2d::Circle { radius }, _) => {
+            let polygon = world_vertices(shape_b, position_b, angle_b);
+            // Normal comes out pointing at the circle, flip it to go from a to b
+            circle_polygon(position_a, *radius, &polygon).map(|m| Manifold2d {
+                normal: -m.normal,
+                ..m
+            })
+        }
+        (_, Collider2d::Circle { radius }) => {
+            let polygon = world_vertices(shape_a, position_a, angle_a);
+            circle_polygon(position_b, *radius, &polygon)
+        }
+        _ => {
+            let polygon_a = world_vertices(shape_a, position_a, angle_a);
+            let polygon_b = world_vertices(shape_b, position_b, angle_b);
+            polygon_polygon(&polygon_a, &polygon_b)
+        }
+    }
+}
+
+fn world_vertices(shape: &Collider2d, position: Vec2, angle: f32) -> Vec<Vec2> {
+    let (sin, cos) = angle.sin_cos();
+    shape
+        .vertices()
+        .into_iter()
+        .map(|v| position + Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos))
+        .collect()
+}
+
+pub fn circle_circle(
+    center_a: Vec2,
+    radius_a: f32,
+    center_b: Vec2,
+    radius_b: f32,
+) -> Option<Manifold2d> {
+    let ab = center_b - center_a;
+    let distance = ab.length();
+    let depth = radius_a + radius_b - distance;
+    if depth < 0.0 {
+        return None;
+    }
+    // Circles on top of each other have no direction, any will do
+    let normal = ab.try_normalize().unwrap_or(Vec2::Y);
+    Some(Manifold2d::single(
+        normal,
+        center_a + normal * (radius_a - depth * 0.5),
+        depth,
+    ))
+}
+
+/// Normal points from the polygon to the circle
+pub fn circle_polygon(center: Vec2, radius: f32, polygon: &[Vec2]) -> Option<Manifold2d> {
+    // Edge the center is furthest outside of
+    let mut best_separation = f32::NEG_INFINITY;
+    let mut best_normal = Vec2::Y;
+    for (i, &v) in polygon.iter().enumerate() {
+        let normal = edge_normal(v, polygon[(i + 1) % polygon.len()]);
+        let separation = normal.dot(center - v);
+        if separation > best_separation {
+            best_separation = separation;
+            best_normal = normal;
+        }
+    }
+    if best_separation > radius {
+        return None;
+    }
+
+    if best_separation <= 0.0 {
+        // Center is inside, push out through the closest edge
+        let depth = radius - best_separation;
+        return Some(Manifold2d::single(
+            best_normal,
+            center - best_normal * (best_separation + depth * 0.5),
+            depth,
+        ));
+    }
+
+    // Closest point on the outline
+    let mut closest = polygon[0];
+    let mut closest_distance_sq = f32::INFINITY;
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let ab = b - a;
+        let t = ((center - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        let point = a + ab * t;
+        let distance_sq = point.distance_squared(center);
+        if distance_sq < closest_distance_sq {
+            closest = point;
+            closest_distance_sq = distance_sq;
+        }
+    }
+    let distance = closest_distance_sq.sqrt();
+    if distance > radius {
+        return None;
+    }
+    let normal = (center - closest).try_normalize().unwrap_or(best_normal);
+    let depth = radius - distance;
+    Some(Manifold2d::single(
+        normal,
+        closest - normal * (depth * 0.5),
+        depth,
+    ))
+}
+
+/// Separating axis test, then the edge of the other polygon facing the axis with the least
+/// overlap is clipped against it, so edges lying on each other touch at both ends
+pub fn polygon_polygon(polygon_a: &[Vec2], polygon_b: &[Vec2]) -> Option<Manifold2d> {
+    let (separation_a, edge_a) = max_separation(polygon_a, polygon_b)?;
+    let (separation_b, edge_b) = max_separation(polygon_b, polygon_a)?;
+
+    // Prefer a, so a resting contact doesnt flip between the two every frame
+    let (reference, incident, edge, flip) = if separation_b > separation_a + REFERENCE_TOLERANCE {
+        (polygon_b, polygon_a, edge_b, true)
+    } else {
+        (polygon_a, polygon_b, edge_a, false)
+    };
+    let v1 = reference[edge];
+    let v2 = reference[(edge + 1) % reference.len()];
+    let normal = edge_normal(v1, v2);
+
+    // Edge of the other polygon facing most against the normal
+    let (_, incident_edge) = (0..incident.len())
+        .map(|i| {
+            let facing = normal.dot(edge_normal(incident[i], incident[(i + 1) % incident.len()]));
+            (facing, i)
+        })
+        .fold((f32::INFINITY, 0), |best, next| {
+            if next.0 < best.0 {
+                next
+            } else {
+                best
+            }
+        });
+    let mut segment = [
+        incident[incident_edge],
+        incident[(incident_edge + 1) % incident.len()],
+    ];
+
+    // Cut it down to the part alongside the reference edge
+    let tangent = (v2 - v1).normalize_or_zero();
+    if !clip(&mut segment, -tangent, -tangent.dot(v1))
+        || !clip(&mut segment, tangent, tangent.dot(v2))
+    {
+        return None;
+    }
+
+    // Only the ends below the reference edge touch, moved halfway back out
+    let points = segment
+        .iter()
+        .filter_map(|&p| {
+            let separation = normal.dot(p - v1);
+            (separation <= 0.0).then(|| ManifoldPoint2d {
+                point: p - normal * (separation * 0.5),
+                depth: -separation,
+            })
+        })
+        .collect::<Vec<_>>();
+    if points.is_empty() {
+        return None;
+    }
+    // Normal goes from a to b
+    Some(Manifold2d {
+        normal: if flip { -normal } else { normal },
+        points,
+    })
+}
+
+// Largest separation of other from any edge of reference, and the index of that edge.
+// None once a separating axis is found
+fn max_separation(reference: &[Vec2], other: &[Vec2]) -> Option<(f32, usize)> {
+    let mut best = (f32::NEG_INFINITY, 0);
+    for (i, &v) in reference.iter().enumerate() {
+        let normal = edge_normal(v, reference[(i + 1) % reference.len()]);
+        let separation = other
+            .iter()
+            .map(|&p| normal.dot(p - v))
+            .fold(f32::INFINITY, f32::min);
+        if separation > 0.0 {
+            return None;
+        }
+        if separation > best.0 {
+            best = (separation, i);
+        }
+    }
+    Some(best)
+}
+
+// Keeps the part of the segment where normal.dot(p) <= offset, false if none of it is
+fn clip(segment: &mut [Vec2; 2], normal: Vec2, offset: f32) -> bool {
+    let d0 = normal.dot(segment[0]) - offset;
+    let d1 = normal.dot(segment[1]) - offset;
+    if d0 > 0.0 && d1 > 0.0 {
+        return false;
+    }
+    if d0 > 0.0 || d1 > 0.0 {
+        let crossing = segment[0] + (segment[1] - segment[0]) * (d0 / (d0 - d1));
+        if d0 > 0.0 {
+            segment[0] = crossing;
+        } else {
+            segment[1] = crossing;
+        }
+    }
+    true
+}
+
+// Outward normal of a counter clockwise edge
+fn edge_normal(a: Vec2, b: Vec2) -> Vec2 {
+    let edge = b - a;
+    Vec2::new(edge.y, -edge.x)
+        .try_normalize()
+        .unwrap_or(Vec2::Y)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::{collide_2d, Collider2d};
+
+    #[test]
+    fn boxes_2d_overlap_along_least_penetration() {
+        let a = Collider2d::Box {
+            half_extents: Vec2::ONE,
+        };
+        let b = Collider2d::Box {
+            half_extents: Vec2::ONE,
+        };
+
+        let manifold = collide_2d(&a, Vec2::ZERO, 0.0, &b, Vec2::new(1.5, 0.2), 0.0).unwrap();
+        assert!((manifold.normal - Vec2::X).length() < 1e-5);
+        assert!((manifold.depth() - 0.5).abs() < 1e-5);
+
+        assert!(collide_2d(&a, Vec2::ZERO, 0.0, &b, Vec2::new(2.5, 0.0), 0.0).is_none());
+    }
+
+    #[test]
+    fn circle_2d_resting_on_box() {
+        let circle = Collider2d::Circle { radius: 0.5 };
+        let ground = Collider2d::Box {
+            half_extents: Vec2::new(10.0, 1.0),
+        };
+
+        // Normal goes from the circle down into the ground
+        let manifold =
+            collide_2d(&circle, Vec2::new(0.0, 1.4), 0.0, &ground, Vec2::ZERO, 0.0).unwrap();
+        assert!((manifold.normal + Vec2::Y).length() < 1e-5);
+        assert!((manifold.depth() - 0.1).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,61 @@
+mod body;
+mod collide;
+mod phases;
+
+pub use body::*;
+pub use collide::*;
+pub use phases::*;
+
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_inspector_egui::RegisterInspectable;
+
+use crate::{run_physics, Phases};
+
+/// Found touching by the 2D narrow phase
+#[derive(Debug)]
+pub struct Contact2d {
+    pub a: Entity,
+    pub b: Entity,
+    /// Points from a to b
+    pub normal: Vec2,
+    /// One, or two when edges lie on each other
+    pub points: Vec<ManifoldPoint2d>,
+    pub elasticity: f32,
+    pub friction: f32,
+}
+
+/// Bodies on the XY plane. Runs in the same phases as the 3D bodies and shares their
+/// broad phase, so add it alongside [crate::PhysicsPlugin].
+/// Always f32, the f64 feature only covers 3D bodies
+pub struct PhysicsPlugin2d;
+impl Plugin for PhysicsPlugin2d {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Contact2d>()
+            .register_inspectable::<Body2d>()
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label(Phases::Setup)
+                    .after(TransformSystem::TransformPropagate)
+                    .with_system(spawn_body2d),
+            )
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .after(Phases::Setup)
+                    .with_run_criteria(run_physics)
+                    .with_system(dynamics2d_system.label(Phases::Dynamics))
+                    .with_system(narrow2d_system.label(Phases::Narrow).after(Phases::Broad))
+                    .with_system(
+                        resolve2d_system
+                            .label(Phases::Resolve)
+                            .after(Phases::Narrow),
+                    )
+                    .with_system(
+                        update_body2d_system
+                            .label(Phases::UpdatePosition)
+                            .after(Phases::Resolve),
+                    ),
+            );
+    }
+}
@@ -0,0 +1,469 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    from_real, to_real, Aabb, BroadContact, GlobalAabb, Mass, PhysicsConfig, PhysicsTime, Vector,
+};
+
+use super::{collide_2d, cross, Body2d, Collider2d, Contact2d};
+
+// Overlap allowed before positions get pushed apart, stops resting bodies from jittering
+const PENETRATION_SLOP: f32 = 0.01;
+const POSITION_CORRECTION: f32 = 0.8;
+
+pub fn spawn_body2d(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Body2d, &mut Collider2d), Added<Body2d>>,
+) {
+    for (e, mut body, mut collider) in query.iter_mut() {
+        collider.make_counter_clockwise();
+        let (inv_mass, inv_inertia) = match body.mass {
+            Mass::Static => (0.0, 0.0),
+            Mass::Value(mass) => {
+                let mass = from_real(mass);
+                let inertia = collider.inertia(mass);
+                let inv_inertia = if inertia > 0.0 { 1.0 / inertia } else { 0.0 };
+                (1.0 / mass, inv_inertia)
+            }
+        };
+        body.inv_mass = inv_mass;
+        body.inv_inertia = inv_inertia;
+
+        // Bounds cover the shape at any angle, so update_aabb can skip rotation.
+        // Z gets the same size so the 3D broad phase sees bodies on the plane overlap
+        let radius = collider.bounding_radius();
+        commands
+            .entity(e)
+            .insert(Aabb {
+                minimums: Vector::splat(-to_real(radius)),
+                maximums: Vector::splat(to_real(radius)),
+            })
+            .insert(GlobalAabb::default()); // will be set by update_aabb
+    }
+}
+
+pub fn dynamics2d_system(mut query: Query<&mut Body2d>, pt: Res<PhysicsTime>) {
+    let dt = from_real(pt.time);
+    for mut body in query.iter_mut() {
+        if body.inv_mass == 0.0 {
+            continue;
+        }
+        body.linear_velocity += Vec2::new(0.0, -10.0) * dt;
+    }
+}
+
+pub fn narrow2d_system(
+    query: Query<(&GlobalTransform, &Body2d, &Collider2d)>,
+    mut broad_contacts: EventReader<BroadContact>,
+    mut contacts: EventWriter<Contact2d>,
+) {
+    for pair in broad_contacts.iter() {
+        if pair.a == pair.b {
+            continue;
+        }
+        // Pairs with a 3D body, or a despawned one, fail here and are left to the 3D phases
+        let (trans_a, body_a, shape_a) = match query.get(pair.a) {
+            Ok(a) => a,
+            Err(_) => continue,
+        };
+        let (trans_b, body_b, shape_b) = match query.get(pair.b) {
+            Ok(b) => b,
+            Err(_) => continue,
+        };
+        if body_a.inv_mass == 0.0 && body_b.inv_mass == 0.0 {
+            continue;
+        }
+
+        if let Some(manifold) = collide_2d(
+            shape_a,
+            trans_a.translation.truncate(),
+            angle_of(trans_a.rotation),
+            shape_b,
+            trans_b.translation.truncate(),
+            angle_of(trans_b.rotation),
+        ) {
+            contacts.send(Contact2d {
+                a: pair.a,
+                b: pair.b,
+                normal: manifold.normal,
+                points: manifold.points,
+                elasticity: body_a.elasticity * body_b.elasticity,
+                friction: body_a.friction * body_b.friction,
+            });
+        }
+    }
+}
+
+pub fn resolve2d_system(
+    mut contacts: EventReader<Contact2d>,
+    mut query: Query<(&mut Body2d, &mut GlobalTransform)>,
+    // Impulses each pair ended last frame with, per point
+    mut last_impulses: Local<HashMap<(Entity, Entity), Vec<(f32, f32)>>>,
+    config: Res<PhysicsConfig>,
+) {
+    // Work on copies, both bodies of a contact cant be borrowed mutably at once
+    let mut bodies: Vec<(Entity, Body2d, GlobalTransform)> = Vec::new();
+    let mut indices = HashMap::default();
+    let mut constraints = Vec::new();
+    for contact in contacts.iter() {
+        if contact.a == contact.b {
+            continue;
+        }
+        let mut index_of = |entity: Entity| {
+            if let Some(&i) = indices.get(&entity) {
+                return Some(i);
+            }
+            let (body, trans) = query.get(entity).ok()?;
+            bodies.push((entity, body.clone(), *trans));
+            indices.insert(entity, bodies.len() - 1);
+            Some(bodies.len() - 1)
+        };
+        let (a, b) = match (index_of(contact.a), index_of(contact.b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+        if bodies[a].1.inv_mass + bodies[b].1.inv_mass == 0.0 {
+            continue;
+        }
+        constraints.push(Constraint2d::new(contact, a, b, &bodies));
+    }
+
+    // Resting contacts need about the same impulses every frame, starting from last frames
+    // leaves the iterations only the change to find
+    for constraint in constraints.iter_mut() {
+        let key = (constraint.contact.a, constraint.contact.b);
+        if let Some(impulses) = last_impulses.get(&key) {
+            with_bodies(&mut bodies, constraint, |c, a, b| {
+                c.warm_start(impulses, a, b)
+            });
+        }
+    }
+
+    // Every contact is solved each iteration, so a push on one box reaches the whole stack
+    for _ in 0..config.solver_iterations.max(1) {
+        for constraint in constraints.iter_mut() {
+            with_bodies(&mut bodies, constraint, |c, a, b| c.solve(a, b));
+        }
+    }
+
+    last_impulses.clear();
+    for constraint in constraints.iter() {
+        last_impulses.insert(
+            (constraint.contact.a, constraint.contact.b),
+            constraint.impulses(),
+        );
+    }
+
+    // Push them apart by the deepest point, split by mass
+    for constraint in constraints.iter() {
+        let inv_mass_a = bodies[constraint.a].1.inv_mass;
+        let inv_mass_b = bodies[constraint.b].1.inv_mass;
+        let depth = constraint
+            .contact
+            .points
+            .iter()
+            .map(|p| p.depth)
+            .fold(0.0, f32::max);
+        let correction = constraint.contact.normal
+            * ((depth - PENETRATION_SLOP).max(0.0) * POSITION_CORRECTION
+                / (inv_mass_a + inv_mass_b));
+        bodies[constraint.a].2.translation -= (correction * inv_mass_a).extend(0.0);
+        bodies[constraint.b].2.translation += (correction * inv_mass_b).extend(0.0);
+    }
+
+    // Write the results back, static bodies never change
+    for (e, body, trans) in bodies
+        .into_iter()
+        .filter(|(_, body, _)| body.inv_mass != 0.0)
+    {
+        if let Ok((mut b, mut t)) = query.get_mut(e) {
+            *b = body;
+            *t = trans;
+        }
+    }
+}
+
+// Runs f on copies of the contacts two bodies, then writes them back
+fn with_bodies(
+    bodies: &mut [(Entity, Body2d, GlobalTransform)],
+    constraint: &mut Constraint2d,
+    f: impl FnOnce(&mut Constraint2d, &mut Body2d, &mut Body2d),
+) {
+    let mut body_a = bodies[constraint.a].1.clone();
+    let mut body_b = bodies[constraint.b].1.clone();
+    f(constraint, &mut body_a, &mut body_b);
+    bodies[constraint.a].1 = body_a;
+    bodies[constraint.b].1 = body_b;
+}
+
+// One contact for this frame, with the impulses applied at each of its points so far
+struct Constraint2d<'a> {
+    contact: &'a Contact2d,
+    a: usize,
+    b: usize,
+    position_a: Vec2,
+    position_b: Vec2,
+    // Bounce back at elasticity times the speed they came together at
+    targets: Vec<f32>,
+    normal_impulses: Vec<f32>,
+    friction_impulses: Vec<f32>,
+}
+
+impl<'a> Constraint2d<'a> {
+    fn new(
+        contact: &'a Contact2d,
+        a: usize,
+        b: usize,
+        bodies: &[(Entity, Body2d, GlobalTransform)],
+    ) -> Self {
+        let mut constraint = Constraint2d {
+            contact,
+            a,
+            b,
+            position_a: bodies[a].2.translation.truncate(),
+            position_b: bodies[b].2.translation.truncate(),
+            targets: Vec::new(),
+            normal_impulses: vec![0.0; contact.points.len()],
+            friction_impulses: vec![0.0; contact.points.len()],
+        };
+        constraint.targets = contact
+            .points
+            .iter()
+            .map(|p| {
+                let approach = constraint.relative(&bodies[a].1, &bodies[b].1, p.point);
+                -contact.elasticity * approach.dot(contact.normal).min(0.0)
+            })
+            .collect();
+        constraint
+    }
+
+    // Velocity of b relative to a
+    fn relative(&self, body_a: &Body2d, body_b: &Body2d, point: Vec2) -> Vec2 {
+        body_b.velocity_at(point, self.position_b) - body_a.velocity_at(point, self.position_a)
+    }
+
+    fn effective_mass(
+        &self,
+        body_a: &Body2d,
+        body_b: &Body2d,
+        point: Vec2,
+        direction: Vec2,
+    ) -> f32 {
+        let ra_cross = cross(point - self.position_a, direction);
+        let rb_cross = cross(point - self.position_b, direction);
+        body_a.inv_mass
+            + body_b.inv_mass
+            + body_a.inv_inertia * ra_cross * ra_cross
+            + body_b.inv_inertia * rb_cross * rb_cross
+    }
+
+    // Applies last frames impulses up front. Points are matched by order, which only holds
+    // while the pair touches the same way, so a different count starts from zero
+    fn warm_start(&mut self, impulses: &[(f32, f32)], body_a: &mut Body2d, body_b: &mut Body2d) {
+        if impulses.len() != self.contact.points.len() {
+            return;
+        }
+        let normal = self.contact.normal;
+        let tangent = Vec2::new(-normal.y, normal.x);
+        for (i, (p, &(normal_impulse, friction_impulse))) in
+            self.contact.points.iter().zip(impulses).enumerate()
+        {
+            let impulse = normal * normal_impulse + tangent * friction_impulse;
+            body_a.apply_impulse(p.point, self.position_a, -impulse);
+            body_b.apply_impulse(p.point, self.position_b, impulse);
+            self.normal_impulses[i] = normal_impulse;
+            self.friction_impulses[i] = friction_impulse;
+        }
+    }
+
+    fn impulses(&self) -> Vec<(f32, f32)> {
+        self.normal_impulses
+            .iter()
+            .copied()
+            .zip(self.friction_impulses.iter().copied())
+            .collect()
+    }
+
+    // Impulses are kept to pushing only, and friction within what the push allows
+    fn solve(&mut self, body_a: &mut Body2d, body_b: &mut Body2d) {
+        let normal = self.contact.normal;
+        let tangent = Vec2::new(-normal.y, normal.x);
+        for (i, p) in self.contact.points.iter().enumerate() {
+            let velocity = self.relative(body_a, body_b, p.point).dot(normal);
+            let mass = self.effective_mass(body_a, body_b, p.point, normal);
+            let total = (self.normal_impulses[i] + (self.targets[i] - velocity) / mass).max(0.0);
+            let impulse = total - self.normal_impulses[i];
+            self.normal_impulses[i] = total;
+            body_a.apply_impulse(p.point, self.position_a, -normal * impulse);
+            body_b.apply_impulse(p.point, self.position_b, normal * impulse);
+
+            // Friction against the sliding, capped by how hard the point is pushing
+            let max_friction = self.contact.friction * self.normal_impulses[i];
+            let velocity = self.relative(body_a, body_b, p.point).dot(tangent);
+            let mass = self.effective_mass(body_a, body_b, p.point, tangent);
+            let total =
+                (self.friction_impulses[i] - velocity / mass).clamp(-max_friction, max_friction);
+            let impulse = total - self.friction_impulses[i];
+            self.friction_impulses[i] = total;
+            body_a.apply_impulse(p.point, self.position_a, -tangent * impulse);
+            body_b.apply_impulse(p.point, self.position_b, tangent * impulse);
+        }
+    }
+}
+
+pub fn update_body2d_system(
+    mut query: Query<(&mut GlobalTransform, &Body2d)>,
+    pt: Res<PhysicsTime>,
+) {
+    let dt = from_real(pt.time);
+    for (mut trans, body) in query.iter_mut() {
+        if body.inv_mass == 0.0 {
+            continue;
+        }
+        trans.translation += (body.linear_velocity * dt).extend(0.0);
+        trans.rotation =
+            (Quat::from_rotation_z(body.angular_velocity * dt) * trans.rotation).normalize();
+    }
+}
+
+/// Rotation around Z, bodies on the plane never turn around anything else
+pub fn angle_of(rotation: Quat) -> f32 {
+    2.0 * rotation.z.atan2(rotation.w)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        dim2::{Body2d, Collider2d, Contact2d},
+        from_real,
+        test_util::{run, test_world},
+        BroadContact, Mass,
+    };
+
+    use super::{
+        angle_of, dynamics2d_system, narrow2d_system, resolve2d_system, spawn_body2d,
+        update_body2d_system,
+    };
+
+    fn spawn_box(world: &mut World, position: Vec2, half_extents: Vec2, mass: Mass) -> Entity {
+        let collider = Collider2d::Box { half_extents };
+        let (inv_mass, inv_inertia) = match mass {
+            Mass::Static => (0.0, 0.0),
+            Mass::Value(mass) => {
+                let mass = from_real(mass);
+                (1.0 / mass, 1.0 / collider.inertia(mass))
+            }
+        };
+        world
+            .spawn()
+            .insert(Body2d {
+                elasticity: 0.0,
+                mass,
+                inv_mass,
+                inv_inertia,
+                ..Default::default()
+            })
+            .insert(collider)
+            .insert(GlobalTransform::from_translation(position.extend(0.0)))
+            .id()
+    }
+
+    // Runs the 2D phases for a number of frames, with the broad phase reporting the given pairs
+    fn step_frames(world: &mut World, pairs: &[(Entity, Entity)], frames: usize) {
+        for _ in 0..frames {
+            world
+                .get_resource_mut::<Events<Contact2d>>()
+                .unwrap()
+                .clear();
+            {
+                let mut broad = world.get_resource_mut::<Events<BroadContact>>().unwrap();
+                broad.clear();
+                for &(a, b) in pairs {
+                    broad.send(BroadContact { a, b });
+                }
+            }
+            run(world, dynamics2d_system);
+            run(world, narrow2d_system);
+            run(world, resolve2d_system);
+            run(world, update_body2d_system);
+        }
+    }
+
+    #[test]
+    fn box_resting_on_box_stays_still() {
+        let mut world = test_world();
+        world.insert_resource(Events::<Contact2d>::default());
+        let ground = spawn_box(&mut world, Vec2::ZERO, Vec2::new(5.0, 0.5), Mass::Static);
+        // Off center, a single contact point would tip it over
+        let start = Vec2::new(0.3, 0.99);
+        let block = spawn_box(&mut world, start, Vec2::splat(0.5), Mass::Value(1.0));
+
+        step_frames(&mut world, &[(block, ground)], 120);
+
+        let contacts = world.get_resource::<Events<Contact2d>>().unwrap();
+        let contact = contacts.get_reader().iter(contacts).next().unwrap();
+        assert_eq!(contact.points.len(), 2);
+
+        let transform = world.get::<GlobalTransform>(block).unwrap();
+        assert!((transform.translation.truncate() - start).length() < 0.02);
+        assert!(angle_of(transform.rotation).abs() < 0.01);
+    }
+
+    #[test]
+    fn box_stack_stays_still() {
+        let mut world = test_world();
+        world.insert_resource(Events::<Contact2d>::default());
+        let ground = spawn_box(&mut world, Vec2::ZERO, Vec2::new(5.0, 0.5), Mass::Static);
+        let starts = [
+            Vec2::new(0.3, 0.99),
+            Vec2::new(0.2, 1.98),
+            Vec2::new(0.1, 2.97),
+        ];
+        let boxes =
+            starts.map(|start| spawn_box(&mut world, start, Vec2::splat(0.5), Mass::Value(1.0)));
+
+        step_frames(
+            &mut world,
+            &[
+                (boxes[0], ground),
+                (boxes[1], boxes[0]),
+                (boxes[2], boxes[1]),
+            ],
+            120,
+        );
+
+        for (e, start) in boxes.into_iter().zip(starts) {
+            let transform = world.get::<GlobalTransform>(e).unwrap();
+            assert!((transform.translation.truncate() - start).length() < 0.02);
+            assert!(angle_of(transform.rotation).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn clockwise_polygon_is_reversed() {
+        let mut world = test_world();
+        let points = vec![
+            Vec2::new(-0.5, -0.5),
+            Vec2::new(-0.5, 0.5),
+            Vec2::new(0.5, 0.5),
+            Vec2::new(0.5, -0.5),
+        ];
+        let e = world
+            .spawn()
+            .insert(Body2d {
+                mass: Mass::Value(1.0),
+                ..Default::default()
+            })
+            .insert(Collider2d::Polygon { points })
+            .id();
+
+        run(&mut world, spawn_body2d);
+
+        assert!(world.get::<Body2d>(e).unwrap().inv_inertia > 0.0);
+        match world.get::<Collider2d>(e).unwrap() {
+            Collider2d::Polygon { points } => assert_eq!(points[0], Vec2::new(0.5, -0.5)),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use crate::{Body, Contact};
+
+/// Edits or discards contacts after the narrow phase, before they are resolved.
+/// Use it for one-way platforms, conveyor belts or per contact materials
+pub trait ContactModifier: Send + Sync + 'static {
+    /// Return false to discard the contact
+    fn modify_contact(&self, contact: &mut Contact, body_a: &Body, body_b: &Body) -> bool;
+}
+
+impl<F> ContactModifier for F
+where
+    F: Fn(&mut Contact, &Body, &Body) -> bool + Send + Sync + 'static,
+{
+    fn modify_contact(&self, contact: &mut Contact, body_a: &Body, body_b: &Body) -> bool {
+        self(contact, body_a, body_b)
+    }
+}
+
+/// Contact modifiers, run in the order they were added
+#[derive(Default)]
+pub struct ContactHooks {
+    modifiers: Vec<Box<dyn ContactModifier>>,
+}
+
+impl ContactHooks {
+    pub fn add(&mut self, modifier: impl ContactModifier) -> &mut Self {
+        self.modifiers.push(Box::new(modifier));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.modifiers.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modifiers.is_empty()
+    }
+
+    /// Returns false if any modifier discarded the contact
+    pub(crate) fn apply(&self, contact: &mut Contact, body_a: &Body, body_b: &Body) -> bool {
+        self.modifiers
+            .iter()
+            .all(|modifier| modifier.modify_contact(contact, body_a, body_b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        narrow_system,
+        test_util::{run, spawn_ball, test_world},
+        Body, BroadContact, CollisionDetection, Contact, PhysicsConfig, Vector,
+    };
+
+    use super::ContactHooks;
+
+    #[test]
+    fn hooks_edit_and_discard_contacts() {
+        let mut world = test_world();
+        world.get_resource_mut::<PhysicsConfig>().unwrap().detection = CollisionDetection::Static;
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        let ghost = spawn_ball(&mut world, -Vector::X);
+        {
+            let mut hooks = world.get_resource_mut::<ContactHooks>().unwrap();
+            hooks.add(move |contact: &mut Contact, _: &Body, _: &Body| {
+                contact.a != ghost && contact.b != ghost
+            });
+            hooks.add(|contact: &mut Contact, _: &Body, _: &Body| {
+                contact.friction = 0.9;
+                true
+            });
+        }
+        {
+            let mut broad = world.get_resource_mut::<Events<BroadContact>>().unwrap();
+            broad.send(BroadContact { a, b });
+            broad.send(BroadContact { a, b: ghost });
+        }
+
+        run(&mut world, narrow_system);
+
+        let events = world.get_resource::<Events<Contact>>().unwrap();
+        let contacts = events.get_reader().iter(events).collect::<Vec<_>>();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!((contacts[0].a, contacts[0].b), (a, b));
+        assert_eq!(contacts[0].friction, 0.9);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{Real, Vector};
+
+/// Slab test, returns the entry and exit time along the ray in units of ray_direction
+pub fn ray_aabb_intersect(
+    ray_start: Vector,
+    ray_direction: Vector,
+    minimums: Vector,
+    maximums: Vector,
+) -> Option<(Real, Real)> {
+    let inv_dir = Vector::ONE / ray_direction;
+    let t_min = (minimums - ray_start) * inv_dir;
+    let t_max = (maximums - ray_start) * inv_dir;
+
+    // recip of zero gives inf, NaN only shows up if the ray starts exactly on a slab
+    // with zero direction, treat that as inside the slab. Checked before min and max,
+    // they drop NaN and would keep the other side
+    let on_slab = t_min.cmpne(t_min) | t_max.cmpne(t_max);
+    let t1 = Vector::select(on_slab, Vector::splat(Real::NEG_INFINITY), t_min.min(t_max));
+    let t2 = Vector::select(on_slab, Vector::splat(Real::INFINITY), t_min.max(t_max));
+
+    let enter = t1.max_element();
+    let exit = t2.min_element();
+    if enter > exit {
+        None
+    } else {
+        Some((enter, exit))
+    }
+}
+
+/// True if the two boxes overlap, touching faces count as overlapping
+pub fn aabb_aabb_intersect(min_a: Vector, max_a: Vector, min_b: Vector, max_b: Vector) -> bool {
+    min_a.cmple(max_b).all() && min_b.cmple(max_a).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vector;
+
+    use super::{aabb_aabb_intersect, ray_aabb_intersect};
+
+    #[test]
+    fn ray_aabb_enter_and_exit() {
+        let hit = |start: Vector| ray_aabb_intersect(start, Vector::X, -Vector::ONE, Vector::ONE);
+
+        assert_eq!(hit(Vector::new(-5.0, 0.0, 0.0)), Some((4.0, 6.0)));
+        // Starting inside enters behind the start
+        assert_eq!(hit(Vector::ZERO), Some((-1.0, 1.0)));
+        // Sliding along a face still counts
+        assert_eq!(hit(Vector::new(-5.0, 1.0, 0.0)), Some((4.0, 6.0)));
+        assert_eq!(hit(Vector::new(-5.0, -1.0, 1.0)), Some((4.0, 6.0)));
+        assert_eq!(hit(Vector::new(-5.0, 2.0, 0.0)), None);
+    }
+
+    #[test]
+    fn ray_aabb_behind_start() {
+        let (_, exit) = ray_aabb_intersect(
+            Vector::new(5.0, 0.0, 0.0),
+            Vector::X,
+            -Vector::ONE,
+            Vector::ONE,
+        )
+        .unwrap();
+        assert!(exit < 0.0);
+    }
+
+    #[test]
+    fn aabb_aabb_touching_faces_overlap() {
+        let (min, max) = (-Vector::ONE, Vector::ONE);
+        assert!(aabb_aabb_intersect(
+            min,
+            max,
+            min + Vector::X,
+            max + Vector::X
+        ));
+        assert!(aabb_aabb_intersect(
+            min,
+            max,
+            min + Vector::X * 2.0,
+            max + Vector::X * 2.0
+        ));
+        assert!(!aabb_aabb_intersect(
+            min,
+            max,
+            min + Vector::X * 2.1,
+            max + Vector::X * 2.1
+        ));
+    }
+}
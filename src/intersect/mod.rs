@@ -1,3 +1,5 @@
+mod aabb;
 mod sphere;
 
-pub use sphere::*;
\ No newline at end of file
+pub use aabb::*;
+pub use sphere::*;
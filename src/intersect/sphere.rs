@@ -1,13 +1,11 @@
-use bevy::math::Vec3;
-
-use crate::Body;
+use crate::{Body, Real, Vector};
 
 pub fn ray_sphere_intersect(
-    ray_start: Vec3,
-    ray_direction: Vec3,
-    sphere_center: Vec3,
-    sphere_radius: f32,
-) -> Option<(f32, f32)> {
+    ray_start: Vector,
+    ray_direction: Vector,
+    sphere_center: Vector,
+    sphere_radius: Real,
+) -> Option<(Real, Real)> {
     let m = sphere_center - ray_start;
     let a = ray_direction.dot(ray_direction);
     let b = m.dot(ray_direction);
@@ -27,11 +25,11 @@ pub fn ray_sphere_intersect(
 }
 
 pub fn sphere_sphere_static(
-    radius_a: f32,
-    radius_b: f32,
-    pos_a: Vec3,
-    pos_b: Vec3,
-) -> Option<(Vec3, Vec3)> {
+    radius_a: Real,
+    radius_b: Real,
+    pos_a: Vector,
+    pos_b: Vector,
+) -> Option<(Vector, Vector)> {
     let ab = pos_b - pos_a;
     let radius_ab = radius_a + radius_b;
     let length_squared = ab.length_squared();
@@ -47,31 +45,53 @@ pub fn sphere_sphere_static(
 }
 
 pub fn sphere_sphere_dynamic(
-    radius_a: f32,
-    radius_b: f32,
+    radius_a: Real,
+    radius_b: Real,
     body_a: &Body,
     body_b: &Body,
-    dt: f32,
-) -> Option<(Vec3, Vec3, f32)> {
-    let relative_velocity = body_a.linear_velocity - body_b.linear_velocity;
+    dt: Real,
+) -> Option<(Vector, Vector, Real)> {
+    sphere_sphere_sweep(
+        radius_a,
+        radius_b,
+        body_a.center_of_mass_world,
+        body_b.center_of_mass_world,
+        body_a.linear_velocity,
+        body_b.linear_velocity,
+        dt,
+    )
+}
 
-    let start_pt_a = body_a.center_of_mass_world;
-    let end_pt_a = body_a.center_of_mass_world + relative_velocity * dt;
+/// Moves both spheres along their velocity for up to dt, returns the points of first contact on a and b
+/// and the time of impact
+pub fn sphere_sphere_sweep(
+    radius_a: Real,
+    radius_b: Real,
+    pos_a: Vector,
+    pos_b: Vector,
+    velocity_a: Vector,
+    velocity_b: Vector,
+    dt: Real,
+) -> Option<(Vector, Vector, Real)> {
+    let relative_velocity = velocity_a - velocity_b;
+
+    let start_pt_a = pos_a;
+    let end_pt_a = pos_a + relative_velocity * dt;
     let ray_dir = end_pt_a - start_pt_a;
 
     let mut t0 = 0.0;
     let mut t1 = 0.0;
 
-    const EPSILON: f32 = 0.001;
-    const EPSILON_SQ: f32 = EPSILON * EPSILON;
+    const EPSILON: Real = 0.001;
+    const EPSILON_SQ: Real = EPSILON * EPSILON;
     if ray_dir.length_squared() < EPSILON_SQ {
         // ray is too short, just check if intersecting
-        let ab = body_b.center_of_mass_world - body_a.center_of_mass_world;
+        let ab = pos_b - pos_a;
         let radius = radius_a + radius_b + EPSILON;
         if ab.length_squared() > radius * radius {
             return None;
         }
-    } else if let Some(toi) = ray_sphere_intersect(body_a.center_of_mass_world, ray_dir, body_b.center_of_mass_world, radius_a + radius_b) {
+    } else if let Some(toi) = ray_sphere_intersect(pos_a, ray_dir, pos_b, radius_a + radius_b) {
         t0 = toi.0;
         t1 = toi.1;
     } else {
@@ -96,8 +116,8 @@ pub fn sphere_sphere_dynamic(
     }
 
     // get the points on the respective points of collision
-    let new_pos_a = body_a.center_of_mass_world + body_a.linear_velocity * toi;
-    let new_pos_b = body_b.center_of_mass_world + body_b.linear_velocity * toi;
+    let new_pos_a = pos_a + velocity_a * toi;
+    let new_pos_b = pos_b + velocity_b * toi;
     let ab = (new_pos_b - new_pos_a).normalize();
 
     let pt_on_a = new_pos_a + ab * radius_a;
@@ -105,3 +125,95 @@ pub fn sphere_sphere_dynamic(
 
     Some((pt_on_a, pt_on_b, toi))
 }
+
+/// Closest point on the sphere to point, and if point was inside. With solid a point inside
+/// projects onto itself, otherwise onto the surface
+pub fn sphere_project_point(
+    center: Vector,
+    radius: Real,
+    point: Vector,
+    solid: bool,
+) -> (Vector, bool) {
+    let offset = point - center;
+    let is_inside = offset.length_squared() <= radius * radius;
+    if is_inside && solid {
+        return (point, true);
+    }
+    // Any direction is as good as another from the exact center
+    let dir = offset.try_normalize().unwrap_or(Vector::Y);
+    (center + dir * radius, is_inside)
+}
+
+/// Gap between the two spheres surfaces, zero if they overlap
+pub fn sphere_sphere_distance(
+    radius_a: Real,
+    radius_b: Real,
+    pos_a: Vector,
+    pos_b: Vector,
+) -> Real {
+    (pos_a.distance(pos_b) - radius_a - radius_b).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vector;
+
+    use super::{ray_sphere_intersect, sphere_sphere_sweep};
+
+    #[test]
+    fn ray_sphere_enter_and_exit() {
+        let start = Vector::new(-5.0, 0.0, 0.0);
+        assert_eq!(
+            ray_sphere_intersect(start, Vector::X, Vector::ZERO, 1.0),
+            Some((4.0, 6.0))
+        );
+        // Direction isnt normalized, times are in units of it
+        assert_eq!(
+            ray_sphere_intersect(start, Vector::X * 2.0, Vector::ZERO, 1.0),
+            Some((2.0, 3.0))
+        );
+        assert_eq!(
+            ray_sphere_intersect(start, Vector::Y, Vector::ZERO, 1.0),
+            None
+        );
+    }
+
+    #[test]
+    fn sphere_sphere_sweep_first_touch() {
+        let sweep = |velocity_a: Vector, dt| {
+            sphere_sphere_sweep(
+                1.0,
+                1.0,
+                Vector::ZERO,
+                Vector::X * 10.0,
+                velocity_a,
+                Vector::ZERO,
+                dt,
+            )
+        };
+
+        let (pt_on_a, pt_on_b, toi) = sweep(Vector::X * 4.0, 5.0).unwrap();
+        assert!((toi - 2.0).abs() < 1e-4);
+        assert!((pt_on_a - Vector::X * 9.0).length() < 1e-4);
+        assert!((pt_on_b - Vector::X * 9.0).length() < 1e-4);
+
+        // Touches only after dt, or moving away
+        assert!(sweep(Vector::X * 4.0, 1.0).is_none());
+        assert!(sweep(Vector::X * -4.0, 5.0).is_none());
+    }
+
+    #[test]
+    fn sphere_sphere_sweep_overlapping_hits_at_start() {
+        let (_, _, toi) = sphere_sphere_sweep(
+            1.0,
+            1.0,
+            Vector::ZERO,
+            Vector::X,
+            Vector::Y,
+            Vector::ZERO,
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(toi, 0.0);
+    }
+}
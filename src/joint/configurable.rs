@@ -0,0 +1,154 @@
+use crate::{JointFrame, JointRow, JointState, Matrix, Quaternion, Real, Vector};
+
+use super::{limit_row, JointMotor, JointSpring};
+
+const AXES: [Vector; 3] = [Vector::X, Vector::Y, Vector::Z];
+
+/// What a single axis of a [ConfigurableJoint] is allowed to do
+#[derive(Debug, Clone, Copy, Default)]
+pub enum AxisMode {
+    #[default]
+    Locked,
+    Free,
+    /// Lower and upper distance, or angle in radians for angular axes
+    Limited(Real, Real),
+    Motor(JointMotor),
+    /// Pulled towards target, a distance or angle like the limits
+    Spring {
+        target: Real,
+        spring: JointSpring,
+    },
+}
+
+/// Each of the three linear and three angular axes set up on its own, in a's local space.
+/// Angles are X then Y then Z euler angles, like any euler angles Y has to stay short of
+/// 90 degrees where X and Z turn about the same axis.
+/// Locked everywhere by default, which makes it a fixed joint
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigurableJoint {
+    pub linear: [AxisMode; 3],
+    pub angular: [AxisMode; 3],
+}
+
+impl ConfigurableJoint {
+    pub(crate) fn build_rows(
+        &self,
+        state: &JointState,
+        frame: &JointFrame,
+        rows: &mut Vec<JointRow>,
+    ) {
+        let offset = frame.anchor_b - frame.anchor_a;
+        // The axes turn with a, so a's lever arm reaches all the way to b's anchor
+        let r_a = frame.r_a + offset;
+        let relative = state.relative_rotation(frame);
+
+        for (i, mode) in self.linear.iter().enumerate() {
+            let axis = frame.rotation_a * AXES[i];
+            let row = JointRow::linear(axis, r_a, frame.r_b);
+            axis_row(*mode, row, axis.dot(offset), frame.dt, rows);
+        }
+        // Split once, angles taken around each axis on their own dont add up to the rotation
+        let (angles, rates) = euler_xyz(relative);
+        for (i, mode) in self.angular.iter().enumerate() {
+            let axis = frame.rotation_a * rates[i];
+            axis_row(*mode, JointRow::angular(axis), angles[i], frame.dt, rows);
+        }
+    }
+}
+
+// value is how far along the axis b currently is
+fn axis_row(mode: AxisMode, row: JointRow, value: Real, dt: Real, rows: &mut Vec<JointRow>) {
+    match mode {
+        AxisMode::Locked => rows.push(row.with_position_error(value, dt)),
+        AxisMode::Free => {}
+        AxisMode::Limited(min, max) => {
+            if let Some(row) = limit_row(row, value, min, max, dt) {
+                rows.push(row);
+            }
+        }
+        AxisMode::Motor(motor) => rows.push(motor.row(row, dt)),
+        AxisMode::Spring { target, spring } => rows.push(spring.row(row, value - target, dt)),
+    }
+}
+
+// Angles with rotation = Rx * Ry * Rz, and the directions the relative angular velocity
+// changes each of them along, its dot with each gives that angles rate
+fn euler_xyz(rotation: Quaternion) -> ([Real; 3], [Vector; 3]) {
+    let m = Matrix::from_quat(rotation);
+    let x = (-m.z_axis.y).atan2(m.z_axis.z);
+    let y = m.z_axis.x.clamp(-1.0, 1.0).asin();
+    let z = (-m.y_axis.x).atan2(m.x_axis.x);
+
+    let (sin_x, cos_x) = x.sin_cos();
+    let (sin_y, cos_y) = y.sin_cos();
+    // Keeps the rows finite at the 90 degree singularity
+    let cos_y = cos_y.max(1e-3);
+    let rates = [
+        Vector::new(1.0, sin_x * sin_y / cos_y, -cos_x * sin_y / cos_y),
+        Vector::new(0.0, cos_x, sin_x),
+        Vector::new(0.0, -sin_x, cos_x) / cos_y,
+    ];
+    ([x, y, z], rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, JointKind, Position, Quaternion, Vector,
+    };
+
+    use super::{euler_xyz, AxisMode, ConfigurableJoint};
+
+    fn rotation(angles: Vector) -> Quaternion {
+        Quaternion::from_rotation_x(angles.x)
+            * Quaternion::from_rotation_y(angles.y)
+            * Quaternion::from_rotation_z(angles.z)
+    }
+
+    #[test]
+    fn euler_angles_and_rates() {
+        let angles = Vector::new(0.3, -0.7, 1.2);
+        let q = rotation(angles);
+        let (found, rates) = euler_xyz(q);
+        assert!(
+            (Vector::from(found) - angles).length() < 1e-4,
+            "{:?}",
+            found
+        );
+
+        // Turning a little, each angle moves at its rate row dotted with the angular velocity
+        let angular_velocity = Vector::new(0.2, -0.5, 0.4);
+        let dt = 1e-3;
+        let (stepped, _) = euler_xyz(Quaternion::from_scaled_axis(angular_velocity * dt) * q);
+        for i in 0..3 {
+            let rate = (stepped[i] - found[i]) / dt;
+            assert!(
+                (rate - rates[i].dot(angular_velocity)).abs() < 1e-2,
+                "axis {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn locked_axis_ignores_the_free_ones() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        // No X angle in this, but twist around X taken on its own would find some
+        world.get_mut::<Position>(b).unwrap().rotation = rotation(Vector::new(0.0, 0.8, 0.8));
+        let kind = JointKind::Configurable(ConfigurableJoint {
+            linear: [AxisMode::Free; 3],
+            angular: [AxisMode::Locked, AxisMode::Free, AxisMode::Free],
+        });
+        spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, kind);
+
+        run(&mut world, resolve_system);
+
+        let angular_velocity = world.get::<Body>(b).unwrap().angular_velocity;
+        assert!(angular_velocity.length() < 1e-3, "{:?}", angular_velocity);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{JointFrame, JointRow, Real, Vector};
+
+use super::{limit_row, JointSpring};
+
+/// Keeps the distance between the anchors within a range, rotation is free.
+/// Covers ropes, rigid rods and springs
+#[derive(Debug, Clone, Copy)]
+pub struct DistanceJoint {
+    pub min_length: Real,
+    pub max_length: Real,
+    /// Length the spring pulls towards
+    pub rest_length: Real,
+    pub spring: Option<JointSpring>,
+}
+
+impl DistanceJoint {
+    /// Can get closer but never further apart than length
+    pub fn rope(length: Real) -> Self {
+        DistanceJoint {
+            min_length: 0.0,
+            max_length: length,
+            rest_length: length,
+            spring: None,
+        }
+    }
+
+    /// Always exactly length apart
+    pub fn rigid(length: Real) -> Self {
+        DistanceJoint {
+            min_length: length,
+            max_length: length,
+            rest_length: length,
+            spring: None,
+        }
+    }
+
+    /// Pulled towards rest_length, free to stretch or squash
+    pub fn spring(rest_length: Real, stiffness: Real, damping: Real) -> Self {
+        DistanceJoint {
+            min_length: 0.0,
+            max_length: Real::INFINITY,
+            rest_length,
+            spring: Some(JointSpring { stiffness, damping }),
+        }
+    }
+
+    pub(crate) fn build_rows(&self, frame: &JointFrame, rows: &mut Vec<JointRow>) {
+        let offset = frame.anchor_b - frame.anchor_a;
+        let length = offset.length();
+        // Anchors on top of each other have no direction, any will do
+        let normal = offset.try_normalize().unwrap_or(Vector::X);
+        let row = JointRow::linear(normal, frame.r_a, frame.r_b);
+
+        if let Some(spring) = self.spring {
+            rows.push(spring.row(row, length - self.rest_length, frame.dt));
+        }
+        if self.min_length >= self.max_length {
+            rows.push(row.with_position_error(length - self.min_length, frame.dt));
+        } else if let Some(row) = limit_row(row, length, self.min_length, self.max_length, frame.dt)
+        {
+            rows.push(row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, JointKind, Vector,
+    };
+
+    use super::DistanceJoint;
+
+    #[test]
+    fn rope_joint_only_pulls_when_taut() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let slack = spawn_ball(&mut world, Vector::X);
+        let taut = spawn_ball(&mut world, Vector::X * 3.0);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        for b in [slack, taut] {
+            world.get_mut::<Body>(b).unwrap().linear_velocity = Vector::X;
+            let kind = JointKind::Distance(DistanceJoint::rope(2.0));
+            spawn_joint(&mut world, a, b, Vector::ZERO, Vector::ZERO, kind);
+        }
+
+        run(&mut world, resolve_system);
+
+        assert_eq!(world.get::<Body>(slack).unwrap().linear_velocity, Vector::X);
+        assert!(world.get::<Body>(taut).unwrap().linear_velocity.x < 0.0);
+    }
+}
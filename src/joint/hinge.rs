@@ -0,0 +1,95 @@
+use crate::{JointFrame, JointRow, JointState, Real, Vector};
+
+use super::{limit_row, point_rows, twist_angle, JointMotor};
+
+/// Revolute joint, b can only rotate around a shared axis through the anchors.
+/// Doors, wheels on axles and elbows
+#[derive(Debug, Clone, Copy)]
+pub struct HingeJoint {
+    /// Rotation axis in a's local space
+    pub axis: Vector,
+    /// Lower and upper angle in radians, None for no limit
+    pub limits: Option<(Real, Real)>,
+    /// Drives the angular velocity around the axis, the max impulse is a torque
+    pub motor: Option<JointMotor>,
+}
+
+impl Default for HingeJoint {
+    fn default() -> Self {
+        HingeJoint {
+            axis: Vector::X,
+            limits: None,
+            motor: None,
+        }
+    }
+}
+
+impl HingeJoint {
+    pub(crate) fn build_rows(
+        &self,
+        state: &JointState,
+        frame: &JointFrame,
+        rows: &mut Vec<JointRow>,
+    ) {
+        point_rows(frame, rows);
+
+        let axis = self.axis.try_normalize().unwrap_or(Vector::X);
+        let axis_a = frame.rotation_a * axis;
+        let axis_b = frame.rotation_b * (state.reference_rotation.conjugate() * axis);
+
+        // Keep b's axis lined up with a's, only rotation around it is left free
+        let error = axis_a.cross(axis_b);
+        let (p, q) = axis_a.any_orthonormal_pair();
+        for perpendicular in [p, q] {
+            rows.push(
+                JointRow::angular(perpendicular)
+                    .with_position_error(perpendicular.dot(error), frame.dt),
+            );
+        }
+
+        let angle = twist_angle(state.relative_rotation(frame), axis);
+        if let Some((min, max)) = self.limits {
+            if let Some(row) = limit_row(JointRow::angular(axis_a), angle, min, max, frame.dt) {
+                rows.push(row);
+            }
+        }
+        if let Some(motor) = self.motor {
+            rows.push(motor.row(JointRow::angular(axis_a), frame.dt));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, JointKind, JointMotor, Vector,
+    };
+
+    use super::HingeJoint;
+
+    #[test]
+    fn hinge_motor_spins_around_axis() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        let kind = JointKind::Hinge(HingeJoint {
+            axis: Vector::Y,
+            motor: Some(JointMotor {
+                target_velocity: 2.0,
+                max_force: 1000.0,
+            }),
+            ..Default::default()
+        });
+        spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, kind);
+
+        run(&mut world, resolve_system);
+
+        // Spins up around the hinge axis only
+        let angular_velocity = world.get::<Body>(b).unwrap().angular_velocity;
+        assert!((angular_velocity.y - 2.0).abs() < 0.01);
+        assert!(angular_velocity.x.abs() < 0.01 && angular_velocity.z.abs() < 0.01);
+    }
+}
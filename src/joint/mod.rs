@@ -0,0 +1,308 @@
+mod configurable;
+mod distance;
+mod hinge;
+mod prismatic;
+mod spherical;
+
+pub use configurable::*;
+pub use distance::*;
+pub use hinge::*;
+pub use prismatic::*;
+pub use spherical::*;
+
+use bevy::prelude::*;
+
+use crate::{to_quaternion, JointRow, Position, Quaternion, Real, Vector, PI};
+
+/// Constrains the motion of two bodies relative to each other. Lives on its own entity,
+/// so a body can have any number of joints
+#[derive(Component, Debug, Clone)]
+pub struct Joint {
+    pub body_a: Entity,
+    pub body_b: Entity,
+    /// Attachment point in a's local space, relative to its transform
+    pub anchor_a: Vector,
+    /// Attachment point in b's local space, relative to its transform
+    pub anchor_b: Vector,
+    pub kind: JointKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum JointKind {
+    Spherical(SphericalJoint),
+    Hinge(HingeJoint),
+    Prismatic(PrismaticJoint),
+    /// Locks all relative motion, for gluing bodies together at runtime
+    Fixed,
+    Distance(DistanceJoint),
+    Configurable(ConfigurableJoint),
+}
+
+/// Soft constraint pulling towards a target. Solved implicitly, so it stays stable
+/// even at high stiffness
+#[derive(Debug, Clone, Copy)]
+pub struct JointSpring {
+    pub stiffness: Real,
+    pub damping: Real,
+}
+
+impl JointSpring {
+    pub(crate) fn row(&self, row: JointRow, error: Real, dt: Real) -> JointRow {
+        row.with_spring(error, self.stiffness, self.damping, dt)
+    }
+}
+
+/// Drives a joint towards a relative velocity, using at most max_force each second.
+/// For angular joints the velocity is in radians a second and the force is a torque
+#[derive(Debug, Clone, Copy)]
+pub struct JointMotor {
+    pub target_velocity: Real,
+    pub max_force: Real,
+}
+
+impl JointMotor {
+    pub(crate) fn row(&self, row: JointRow, dt: Real) -> JointRow {
+        let max_impulse = self.max_force * dt;
+        row.with_target_velocity(self.target_velocity)
+            .with_limits(-max_impulse, max_impulse)
+    }
+}
+
+/// Makes a joint break once holding the bodies together takes more than this.
+/// The joint entity is despawned and [JointBroken] sent
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BreakableJoint {
+    pub max_force: Real,
+    pub max_torque: Real,
+}
+
+impl Default for BreakableJoint {
+    fn default() -> Self {
+        BreakableJoint {
+            max_force: Real::INFINITY,
+            max_torque: Real::INFINITY,
+        }
+    }
+}
+
+/// Sent when a [BreakableJoint] snaps, the joint entity is already queued for despawn
+#[derive(Debug)]
+pub struct JointBroken {
+    pub joint: Entity,
+    pub body_a: Entity,
+    pub body_b: Entity,
+}
+
+/// Solver state kept between frames, added to joints during setup
+#[derive(Component, Debug, Clone)]
+pub struct JointState {
+    /// Rotation of b relative to a when the joint was created
+    pub reference_rotation: Quaternion,
+}
+
+impl JointState {
+    /// Rotation of b relative to a in a's local space, identity when they are as they were
+    /// when the joint was made
+    pub fn relative_rotation(&self, frame: &JointFrame) -> Quaternion {
+        frame.rotation_a.conjugate() * frame.rotation_b * self.reference_rotation.conjugate()
+    }
+}
+
+/// World space data a joint builds its rows from
+pub struct JointFrame {
+    /// Anchors in world space
+    pub anchor_a: Vector,
+    pub anchor_b: Vector,
+    /// Anchors relative to each bodies center of mass
+    pub r_a: Vector,
+    pub r_b: Vector,
+    pub rotation_a: Quaternion,
+    pub rotation_b: Quaternion,
+    pub dt: Real,
+}
+
+impl Joint {
+    /// Pushes the velocity constraints for this frame
+    pub fn build_rows(&self, state: &JointState, frame: &JointFrame, rows: &mut Vec<JointRow>) {
+        match &self.kind {
+            JointKind::Spherical(spherical) => spherical.build_rows(state, frame, rows),
+            JointKind::Hinge(hinge) => hinge.build_rows(state, frame, rows),
+            JointKind::Prismatic(prismatic) => prismatic.build_rows(state, frame, rows),
+            JointKind::Fixed => {
+                point_rows(frame, rows);
+                angular_lock_rows(state, frame, rows);
+            }
+            JointKind::Distance(distance) => distance.build_rows(frame, rows),
+            JointKind::Configurable(configurable) => configurable.build_rows(state, frame, rows),
+        }
+    }
+}
+
+// Three rows keeping the anchors coincident
+pub(crate) fn point_rows(frame: &JointFrame, rows: &mut Vec<JointRow>) {
+    let error = frame.anchor_b - frame.anchor_a;
+    for axis in [Vector::X, Vector::Y, Vector::Z] {
+        rows.push(
+            JointRow::linear(axis, frame.r_a, frame.r_b)
+                .with_position_error(axis.dot(error), frame.dt),
+        );
+    }
+}
+
+// Three rows keeping the relative rotation as it was when the joint was made
+pub(crate) fn angular_lock_rows(state: &JointState, frame: &JointFrame, rows: &mut Vec<JointRow>) {
+    let relative = state.relative_rotation(frame);
+    // Small angle approximation of the rotation still needed, taking the short way round
+    let sign = if relative.w < 0.0 { -1.0 } else { 1.0 };
+    let error = frame.rotation_a * (2.0 * sign * Vector::new(relative.x, relative.y, relative.z));
+    for axis in [Vector::X, Vector::Y, Vector::Z] {
+        rows.push(JointRow::angular(axis).with_position_error(axis.dot(error), frame.dt));
+    }
+}
+
+// Angle of the rotation around the axis, from -PI to PI
+pub(crate) fn twist_angle(rotation: Quaternion, axis: Vector) -> Real {
+    let v = Vector::new(rotation.x, rotation.y, rotation.z);
+    let angle = 2.0 * v.dot(axis).atan2(rotation.w);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+// Only pushes back once the angle or distance is past a limit, row is along the direction
+// the value grows in
+pub(crate) fn limit_row(
+    row: JointRow,
+    value: Real,
+    min: Real,
+    max: Real,
+    dt: Real,
+) -> Option<JointRow> {
+    if value < min {
+        Some(
+            row.with_position_error(value - min, dt)
+                .with_limits(0.0, Real::INFINITY),
+        )
+    } else if value > max {
+        Some(
+            row.with_position_error(value - max, dt)
+                .with_limits(Real::NEG_INFINITY, 0.0),
+        )
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn spawn_joint(
+    mut commands: Commands,
+    joints: Query<(Entity, &Joint), Added<Joint>>,
+    positions: Query<(Option<&Position>, Option<&GlobalTransform>)>,
+) {
+    // Bodies spawned this frame only get a position once spawn_bodys commands run,
+    // until then they are wherever their transform is
+    let rotation_of = |entity| match positions.get(entity) {
+        Ok((Some(position), _)) => position.rotation,
+        Ok((None, Some(trans))) => to_quaternion(trans.rotation),
+        _ => Quaternion::IDENTITY,
+    };
+    for (e, joint) in joints.iter() {
+        let rotation_a = rotation_of(joint.body_a);
+        let rotation_b = rotation_of(joint.body_b);
+
+        commands.entity(e).insert(JointState {
+            reference_rotation: rotation_a.conjugate() * rotation_b,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, Joint, JointState, Position, Quaternion, Vector,
+    };
+
+    use super::{BreakableJoint, JointBroken, JointKind};
+
+    #[test]
+    fn joint_keeps_rotation_of_bodies_without_position() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        // Spawned this frame, b only has its transform so far
+        let rotation = Quat::from_rotation_y(0.5);
+        world
+            .entity_mut(b)
+            .remove::<Position>()
+            .insert(GlobalTransform::from_rotation(rotation));
+        let joint = world
+            .spawn()
+            .insert(Joint {
+                body_a: a,
+                body_b: b,
+                anchor_a: Vector::X,
+                anchor_b: Vector::ZERO,
+                kind: JointKind::Fixed,
+            })
+            .id();
+
+        run(&mut world, super::spawn_joint);
+
+        let state = world.get::<JointState>(joint).unwrap();
+        let expected = Quaternion::from_rotation_y(0.5);
+        assert!(state.reference_rotation.dot(expected).abs() > 1.0 - 1e-5);
+    }
+
+    #[test]
+    fn fixed_joint_stops_relative_motion() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        {
+            let mut body = world.get_mut::<Body>(b).unwrap();
+            body.linear_velocity = Vector::Y;
+            body.angular_velocity = Vector::Z;
+        }
+        spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, JointKind::Fixed);
+
+        run(&mut world, resolve_system);
+
+        let body = world.get::<Body>(b).unwrap();
+        assert!(body.linear_velocity.length() < 0.01);
+        assert!(body.angular_velocity.length() < 0.01);
+    }
+
+    #[test]
+    fn overloaded_joint_breaks() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        world.get_mut::<Body>(b).unwrap().linear_velocity = Vector::X * 10.0;
+        let joint = spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, JointKind::Fixed);
+        world.entity_mut(joint).insert(BreakableJoint {
+            max_force: 1.0,
+            ..Default::default()
+        });
+
+        run(&mut world, resolve_system);
+
+        assert!(world.get_entity(joint).is_none());
+        let events = world.get_resource::<Events<JointBroken>>().unwrap();
+        let broken = events.get_reader().iter(events).collect::<Vec<_>>();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(
+            (broken[0].joint, broken[0].body_a, broken[0].body_b),
+            (joint, a, b)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{JointFrame, JointRow, JointState, Real, Vector};
+
+use super::{angular_lock_rows, limit_row, JointMotor};
+
+/// Slider, b can only move along an axis fixed in a and can't rotate relative to it.
+/// Pistons, elevators and drawers
+#[derive(Debug, Clone, Copy)]
+pub struct PrismaticJoint {
+    /// Slide axis in a's local space
+    pub axis: Vector,
+    /// Lower and upper distance between the anchors along the axis, None for no limit
+    pub limits: Option<(Real, Real)>,
+    /// Drives the velocity along the axis
+    pub motor: Option<JointMotor>,
+}
+
+impl Default for PrismaticJoint {
+    fn default() -> Self {
+        PrismaticJoint {
+            axis: Vector::X,
+            limits: None,
+            motor: None,
+        }
+    }
+}
+
+impl PrismaticJoint {
+    pub(crate) fn build_rows(
+        &self,
+        state: &JointState,
+        frame: &JointFrame,
+        rows: &mut Vec<JointRow>,
+    ) {
+        angular_lock_rows(state, frame, rows);
+
+        let axis = frame.rotation_a * self.axis.try_normalize().unwrap_or(Vector::X);
+        let offset = frame.anchor_b - frame.anchor_a;
+        // The axis turns with a, so a's lever arm reaches all the way to b's anchor
+        let r_a = frame.r_a + offset;
+
+        let (p, q) = axis.any_orthonormal_pair();
+        for perpendicular in [p, q] {
+            rows.push(
+                JointRow::linear(perpendicular, r_a, frame.r_b)
+                    .with_position_error(perpendicular.dot(offset), frame.dt),
+            );
+        }
+
+        let along = JointRow::linear(axis, r_a, frame.r_b);
+        if let Some((min, max)) = self.limits {
+            if let Some(row) = limit_row(along, axis.dot(offset), min, max, frame.dt) {
+                rows.push(row);
+            }
+        }
+        if let Some(motor) = self.motor {
+            rows.push(motor.row(along, frame.dt));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, JointKind, JointMotor, Vector,
+    };
+
+    use super::PrismaticJoint;
+
+    // Static a at the origin and b at translation sliding along X, returns b's velocity after solving
+    fn solve_slider(translation: Vector, velocity: Vector, prismatic: PrismaticJoint) -> Vector {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, translation);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        world.get_mut::<Body>(b).unwrap().linear_velocity = velocity;
+        let kind = JointKind::Prismatic(prismatic);
+        spawn_joint(&mut world, a, b, Vector::ZERO, Vector::ZERO, kind);
+
+        run(&mut world, resolve_system);
+
+        world.get::<Body>(b).unwrap().linear_velocity
+    }
+
+    #[test]
+    fn slide_limit() {
+        let limited = PrismaticJoint {
+            limits: Some((0.0, 1.5)),
+            ..Default::default()
+        };
+        // Free to slide inside the limits
+        let velocity = solve_slider(Vector::X, Vector::X * 3.0, limited);
+        assert!(
+            (velocity - Vector::X * 3.0).length() < 0.01,
+            "{:?}",
+            velocity
+        );
+        // Past the upper limit it gets pulled back
+        let velocity = solve_slider(Vector::X * 2.0, Vector::X * 3.0, limited);
+        assert!(velocity.x < 0.0, "{:?}", velocity);
+    }
+
+    #[test]
+    fn linear_motor() {
+        let motor = PrismaticJoint {
+            motor: Some(JointMotor {
+                target_velocity: 2.0,
+                max_force: 1000.0,
+            }),
+            ..Default::default()
+        };
+        let velocity = solve_slider(Vector::X, Vector::ZERO, motor);
+        assert!(
+            (velocity - Vector::X * 2.0).length() < 0.01,
+            "{:?}",
+            velocity
+        );
+    }
+
+    #[test]
+    fn perpendicular_motion_locked() {
+        // Sideways velocity is removed, motion along the axis is left alone
+        let velocity = solve_slider(
+            Vector::X,
+            Vector::new(1.0, 3.0, -2.0),
+            PrismaticJoint::default(),
+        );
+        assert!((velocity - Vector::X).length() < 0.01, "{:?}", velocity);
+
+        // Off the axis it gets pulled back on
+        let velocity = solve_slider(
+            Vector::new(1.0, 0.5, 0.0),
+            Vector::ZERO,
+            PrismaticJoint::default(),
+        );
+        assert!(
+            velocity.y < 0.0 && velocity.x.abs() < 0.01,
+            "{:?}",
+            velocity
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{JointFrame, JointRow, JointState, Real, Vector};
+
+use super::{limit_row, point_rows, twist_angle};
+
+/// Ball and socket, keeps the anchors together and lets b rotate freely around them
+/// unless limited. Used for chains, pendulums and ragdoll shoulders and hips
+#[derive(Debug, Clone, Copy)]
+pub struct SphericalJoint {
+    /// Twist axis in a's local space, the cone is centered on it
+    pub axis: Vector,
+    /// Largest angle in radians b's twist axis may lean away from a's, None for no limit
+    pub swing_limit: Option<Real>,
+    /// Lower and upper twist angle in radians around the axis, None for no limit
+    pub twist_limit: Option<(Real, Real)>,
+}
+
+impl Default for SphericalJoint {
+    fn default() -> Self {
+        SphericalJoint {
+            axis: Vector::X,
+            swing_limit: None,
+            twist_limit: None,
+        }
+    }
+}
+
+impl SphericalJoint {
+    pub(crate) fn build_rows(
+        &self,
+        state: &JointState,
+        frame: &JointFrame,
+        rows: &mut Vec<JointRow>,
+    ) {
+        point_rows(frame, rows);
+
+        let axis = self.axis.try_normalize().unwrap_or(Vector::X);
+        // b's copy of the axis, as it was lined up with a's when the joint was made
+        let axis_a = frame.rotation_a * axis;
+        let axis_b = frame.rotation_b * (state.reference_rotation.conjugate() * axis);
+
+        if let Some(limit) = self.swing_limit {
+            let swing = axis_a.dot(axis_b).clamp(-1.0, 1.0).acos();
+            if swing > limit {
+                // Rotating b around this leans it further out of the cone
+                let normal = axis_a
+                    .cross(axis_b)
+                    .try_normalize()
+                    .unwrap_or_else(|| axis_a.any_orthonormal_pair().0);
+                rows.push(
+                    JointRow::angular(normal)
+                        .with_position_error(swing - limit, frame.dt)
+                        .with_limits(Real::NEG_INFINITY, 0.0),
+                );
+            }
+        }
+
+        if let Some((min, max)) = self.twist_limit {
+            let angle = twist_angle(state.relative_rotation(frame), axis);
+            if let Some(row) = limit_row(JointRow::angular(axis_a), angle, min, max, frame.dt) {
+                rows.push(row);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        resolve_system,
+        test_util::{run, spawn_ball, spawn_joint, test_world},
+        Body, JointKind, Position, Quaternion, Vector,
+    };
+
+    use super::SphericalJoint;
+
+    #[test]
+    fn spherical_joint_pulls_anchors_together() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X * 3.0);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        let kind = JointKind::Spherical(SphericalJoint::default());
+        spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, kind);
+
+        run(&mut world, resolve_system);
+
+        // b sits two units past the anchor, so it has to start moving back
+        assert!(world.get::<Body>(b).unwrap().linear_velocity.x < 0.0);
+        assert_eq!(world.get::<Body>(a).unwrap().linear_velocity, Vector::ZERO);
+    }
+
+    #[test]
+    fn spherical_joint_twist_limit() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().inv_mass = 0.0;
+        world.get_mut::<Position>(b).unwrap().rotation = Quaternion::from_rotation_x(1.0);
+        let kind = JointKind::Spherical(SphericalJoint {
+            twist_limit: Some((-0.5, 0.5)),
+            ..Default::default()
+        });
+        spawn_joint(&mut world, a, b, Vector::X, Vector::ZERO, kind);
+
+        run(&mut world, resolve_system);
+
+        // Twisted half a radian past the limit, it has to turn back
+        assert!(world.get::<Body>(b).unwrap().angular_velocity.x < 0.0);
+    }
+}
@@ -1,19 +1,35 @@
 #[allow(clippy::type_complexity)]
 mod body;
 mod bounds;
+mod broadphase;
 mod collider;
 mod contact;
 mod debug;
+mod dim2;
+mod hooks;
 mod intersect;
+mod joint;
+mod math;
 mod phases;
+mod query;
+mod solver;
+#[cfg(test)]
+mod test_util;
 
 pub use body::*;
 pub use bounds::*;
+pub use broadphase::*;
 pub use collider::*;
 pub use contact::*;
 pub use debug::*;
+pub use dim2::*;
+pub use hooks::*;
 pub use intersect::*;
+pub use joint::*;
+pub use math::*;
 pub use phases::*;
+pub use query::*;
+pub use solver::*;
 
 use bevy::{ecs::schedule::ShouldRun, prelude::*, transform::TransformSystem};
 use bevy_inspector_egui::{Inspectable, RegisterInspectable};
@@ -24,6 +40,11 @@ pub struct PhysicsConfig {
     pub enabled: bool,
     pub debug: bool,
     pub detection: CollisionDetection,
+    pub broadphase: BroadphaseMode,
+    /// Cell size for [BroadphaseMode::SpatialHash], 0 uses the median body size
+    pub grid_cell_size: Real,
+    /// Velocity iterations for contacts and joints
+    pub solver_iterations: usize,
 }
 
 impl Default for PhysicsConfig {
@@ -32,6 +53,9 @@ impl Default for PhysicsConfig {
             enabled: true,
             debug: true,
             detection: CollisionDetection::Dynamic,
+            broadphase: BroadphaseMode::SweepAndPrune,
+            grid_cell_size: 0.0,
+            solver_iterations: 8,
         }
     }
 }
@@ -42,9 +66,20 @@ pub enum CollisionDetection {
     Dynamic, //Continuous,
 }
 
+/// How the broad phase finds possible pairs, scene queries use the same structure
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadphaseMode {
+    /// Sorts along the axis with the most spread, fast for scenes spread out along one axis
+    SweepAndPrune,
+    /// Dynamic AABB tree, for scenes spread out on every axis
+    Bvh,
+    /// Uniform grid, for lots of bodies of about the same size
+    SpatialHash,
+}
+
 #[derive(Default)]
 pub struct PhysicsTime {
-    pub time: f32,
+    pub time: Real,
 }
 
 /// The names of system labels for run order
@@ -64,6 +99,10 @@ pub enum Phases {
 //.with_run_criteria(FixedTimestep::step(FIXED_TIMESTEP))
 // Update update_time_system if you add this back
 
+/// Simulates entities with a [Body]. A body is placed by its transform when it spawns,
+/// from then on the simulation keeps it in a [Position] and only writes the transform for
+/// rendering. Writing the transform of a body after that has no effect, move it through
+/// its [Position]
 pub struct PhysicsPlugin;
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
@@ -73,11 +112,19 @@ impl Plugin for PhysicsPlugin {
             .init_resource::<PhysicsTime>()
             .add_event::<BroadContact>()
             .add_event::<Contact>()
-            .register_inspectable::<Body>()
-            .register_inspectable::<Aabb>()
-            .register_inspectable::<GlobalAabb>()
+            .add_event::<ContactImpulse>()
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionEnded>()
+            .add_event::<JointBroken>()
+            .init_resource::<CollisionPairs>()
+            .init_resource::<ContactHooks>()
+            .init_resource::<SweepAndPrune>()
+            .init_resource::<Bvh>()
+            .init_resource::<SpatialHashGrid>()
+            .init_resource::<BroadphaseSynced>()
             .register_inspectable::<ColliderType>()
             .register_inspectable::<ColliderSphere>()
+            .register_inspectable::<ContactImpulseThreshold>()
             .add_system_set_to_stage(
                 CoreStage::PostUpdate,
                 SystemSet::new()
@@ -87,6 +134,7 @@ impl Plugin for PhysicsPlugin {
                     .with_system(spawn::<ColliderSphere>.label("setup_2").after("setup_1"))
                     .with_system(update_body.label("setup_3").after("setup_2"))
                     .with_system(update_aabb.label("setup_3").after("setup_2"))
+                    .with_system(spawn_joint)
                     .with_system(update_time_system),
             )
             .add_system_set_to_stage(
@@ -96,19 +144,38 @@ impl Plugin for PhysicsPlugin {
                     .with_run_criteria(run_physics)
                     .with_system(dynamics_system.label(Phases::Dynamics))
                     .with_system(broadphase_system.label(Phases::Broad).after(Phases::Dynamics))
+                    .with_system(bvh_broadphase_system.label(Phases::Broad).after(Phases::Dynamics))
+                    .with_system(grid_broadphase_system.label(Phases::Broad).after(Phases::Dynamics))
                     .with_system(narrow_system.label(Phases::Narrow).after(Phases::Broad))
                     .with_system(resolve_system.label(Phases::Resolve).after(Phases::Narrow))
+                    .with_system(collision_events_system.after(Phases::Narrow))
                     .with_system(
                         update_body_system
                             .label(Phases::UpdatePosition)
                             .after(Phases::Resolve),
-                    ),
+                    )
+                    .with_system(sync_transform_system.after(Phases::UpdatePosition)),
+            )
+            // Scene queries read the broad phase, keep it where bodies are even while paused
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .after(Phases::Setup)
+                    .after(Phases::UpdatePosition)
+                    .with_system(update_aabb.label("sync_1"))
+                    .with_system(sync_broadphase_system.after("sync_1")),
             )
 ;
+
+        // The inspector only edits f32 fields
+        #[cfg(not(feature = "f64"))]
+        app.register_inspectable::<Body>()
+            .register_inspectable::<Aabb>()
+            .register_inspectable::<GlobalAabb>();
     }
 }
 
-fn run_physics(config: Res<PhysicsConfig>) -> ShouldRun {
+pub(crate) fn run_physics(config: Res<PhysicsConfig>) -> ShouldRun {
     if config.enabled {
         ShouldRun::Yes
     } else {
@@ -119,36 +186,73 @@ fn run_physics(config: Res<PhysicsConfig>) -> ShouldRun {
 
 
 fn update_time_system(time: Res<Time>, mut pt: ResMut<PhysicsTime>) {
-    pt.time = time.delta_seconds();
+    pt.time = to_real(time.delta_seconds());
 }
 
 #[allow(clippy::type_complexity)]
-pub fn spawn_body(mut query: Query<&mut Body, Added<Body>>) {
-    for mut body in query.iter_mut() {
+pub fn spawn_body(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &mut Body,
+            Option<&GlobalTransform>,
+            Option<&Position>,
+        ),
+        Added<Body>,
+    >,
+) {
+    for (e, mut body, trans, position) in query.iter_mut() {
         body.inv_mass = match body.mass {
             Mass::Static => 0.0,
             Mass::Value(v) => 1.0 / v,
         };
+        // Bodies placed with only a transform start where it is
+        if position.is_none() {
+            commands
+                .entity(e)
+                .insert(trans.map_or_else(Position::default, Position::from_transform));
+        }
     }
 }
 
 
 
-pub fn update_body(mut query: Query<(&mut Body, &GlobalTransform)>) {
-    for (mut body, trans) in query.iter_mut() {
-        body.center_of_mass_world = trans.translation + trans.rotation * body.center_of_mass;
-        let orientation = Mat3::from_quat(trans.rotation);
+pub fn update_body(mut query: Query<(&mut Body, &Position, Option<&LockedAxes>)>) {
+    for (mut body, p, locked) in query.iter_mut() {
+        body.center_of_mass_world = p.translation + p.rotation * body.center_of_mass;
+        let orientation = Matrix::from_quat(p.rotation);
         body.inverse_inertia_tensor_world =
             orientation * body.inverse_inertia_tensor_local * orientation.transpose();
+        if let Some(locked) = locked {
+            body.inverse_inertia_tensor_world =
+                locked.lock_inertia(body.inverse_inertia_tensor_world);
+        }
     }
 }
 
-pub fn update_aabb(mut query: Query<(&GlobalTransform, &Aabb, &mut GlobalAabb)>) {
-    for (trans, aabb, mut global_aabb) in query.iter_mut() {
+#[allow(clippy::type_complexity)]
+pub fn update_aabb(
+    mut query: Query<(Option<&Position>, &GlobalTransform, &Aabb, &mut GlobalAabb)>,
+    removed: RemovedComponents<GlobalAabb>,
+    mut synced: ResMut<BroadphaseSynced>,
+) {
+    for (position, trans, aabb, mut global_aabb) in query.iter_mut() {
+        // 2D bodies have no position, they still move their transform
+        let translation = position.map_or_else(|| to_vector(trans.translation), |p| p.translation);
 
         // TODO: We dont account for rotation yet, but spheres dont need it
-        global_aabb.minimums = trans.translation + aabb.minimums;
-        global_aabb.maximums = trans.translation + aabb.maximums;
+        let minimums = translation + aabb.minimums;
+        let maximums = translation + aabb.maximums;
+        // Only written when they change, the broad phase skips syncing if nothing did
+        if global_aabb.minimums != minimums || global_aabb.maximums != maximums {
+            global_aabb.minimums = minimums;
+            global_aabb.maximums = maximums;
+            synced.0 = false;
+        }
+    }
+    if removed.iter().next().is_some() {
+        synced.0 = false;
     }
 }
 
@@ -167,7 +271,8 @@ pub fn spawn<T: 'static + Component + Collider>(
             .entity(e)
             .insert(collider.get_type())
             .insert(collider.get_aabb())
-            .insert( GlobalAabb::default()); // will be set by update_aabb
+            .insert( GlobalAabb::default()) // will be set by update_aabb
+            .insert(CollidingEntities::default());
     }
 }
 
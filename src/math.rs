@@ -0,0 +1,85 @@
+use bevy::math::{Quat, Vec3};
+
+// Everything the simulation stores is in these types, f64 with the f64 feature.
+// Bodies live in a Position, bevy transforms are always f32 and only written from it for rendering.
+// The 2D plugin doesn't use these, its bodies are always f32
+
+#[cfg(not(feature = "f64"))]
+mod precision {
+    pub type Real = f32;
+    pub type Vector = bevy::math::Vec3;
+    pub type Matrix = bevy::math::Mat3;
+    pub type Quaternion = bevy::math::Quat;
+    pub(crate) use std::f32::consts::PI;
+}
+
+#[cfg(feature = "f64")]
+mod precision {
+    pub type Real = f64;
+    pub type Vector = bevy::math::DVec3;
+    pub type Matrix = bevy::math::DMat3;
+    pub type Quaternion = bevy::math::DQuat;
+    pub(crate) use std::f64::consts::PI;
+}
+
+pub use precision::*;
+
+#[cfg(not(feature = "f64"))]
+mod convert {
+    use super::*;
+
+    pub fn to_real(value: f32) -> Real {
+        value
+    }
+
+    pub fn from_real(value: Real) -> f32 {
+        value
+    }
+
+    pub fn to_vector(value: Vec3) -> Vector {
+        value
+    }
+
+    pub fn from_vector(value: Vector) -> Vec3 {
+        value
+    }
+
+    pub fn to_quaternion(value: Quat) -> Quaternion {
+        value
+    }
+
+    pub fn from_quaternion(value: Quaternion) -> Quat {
+        value
+    }
+}
+
+#[cfg(feature = "f64")]
+mod convert {
+    use super::*;
+
+    pub fn to_real(value: f32) -> Real {
+        value as f64
+    }
+
+    pub fn from_real(value: Real) -> f32 {
+        value as f32
+    }
+
+    pub fn to_vector(value: Vec3) -> Vector {
+        value.as_dvec3()
+    }
+
+    pub fn from_vector(value: Vector) -> Vec3 {
+        value.as_vec3()
+    }
+
+    pub fn to_quaternion(value: Quat) -> Quaternion {
+        value.as_f64()
+    }
+
+    pub fn from_quaternion(value: Quaternion) -> Quat {
+        value.as_f32()
+    }
+}
+
+pub use convert::*;
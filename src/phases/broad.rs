@@ -1,58 +1,80 @@
 use bevy::prelude::*;
 
-use crate::{BroadContact, GlobalAabb};
+use crate::{
+    BroadContact, BroadphaseMode, Bvh, GlobalAabb, PhysicsConfig, Real, SpatialHashGrid,
+    SweepAndPrune,
+};
+
+/// Set once the active broad phase holds the bounds update_aabb last wrote, so it isnt synced
+/// twice when nothing moved in between. Cleared by update_aabb when any bounds change
+#[derive(Default)]
+pub struct BroadphaseSynced(pub(crate) bool);
 
 // The board phase is responsible for pruning the search space of possable collisions
-// I have tried different approaches, and I am sure I will try a few more
-// So far this simple approach has been the fastest
-// TODO: Figure out way to search two axis thats actually faster or bite the bullet and try some space partitioning
+// There are three to pick from with PhysicsConfig::broadphase. Sweep and prune is the default
+// and fastest when bodies are spread out along one axis, the bvh when they are spread out on
+// every axis, and the grid with lots of bodies about the same size
 pub fn broadphase_system(
     mut broad_contacts: EventWriter<BroadContact>,
+    mut sap: ResMut<SweepAndPrune>,
     query: Query<(Entity, &GlobalAabb)>,
+    added: Query<Entity, Added<GlobalAabb>>,
+    synced: Res<BroadphaseSynced>,
+    config: Res<PhysicsConfig>,
 ) {
-    //let t0 = Instant::now();
-    // TODO: Yes, we are copying the array out here, only way to sort it
-    // Ideally we would keep the array around, it should already near sorted
-    let mut list = query.iter().collect::<Vec<_>>();
+    // Let it go while another broad phase is used, it gets rebuilt if we switch back
+    if config.broadphase != BroadphaseMode::SweepAndPrune {
+        if !sap.is_empty() {
+            sap.clear();
+        }
+        return;
+    }
 
-    // Sort the array on currently selected sorting axis
-    // Note: Update inter loop if you change the axis
-    list.sort_unstable_by(cmp_x_axis);
+    //let t0 = Instant::now();
+    // Already up to date if nothing moved since the end of last frame
+    if !synced.0 || sap.is_empty() {
+        sync_sweep_and_prune(&mut sap, &query, &added);
+    }
+    let axis = sap.axis;
+    let list = &sap.entries;
 
     //let t1 = Instant::now();
     // Sweep the array for collisions
-    for (i, (a, aabb_a)) in list.iter().enumerate() {
+    for (i, a) in list.iter().enumerate() {
         // Test collisions against all possible overlapping AABBs following current one
-        for (b, aabb_b) in list.iter().skip(i + 1) {
+        for b in list.iter().skip(i + 1) {
             // Stop when tested AABBs are beyond the end of current AABB
-            if aabb_b.minimums.x > aabb_a.maximums.x {
+            if b.minimums[axis] > a.maximums[axis] {
                 break;
             }
 
             // SAT test
-            if aabb_a.minimums.x >= aabb_b.maximums.x {
+            if a.minimums.x >= b.maximums.x {
                 continue;
             }
-            if aabb_a.maximums.x <= aabb_b.minimums.x {
+            if a.maximums.x <= b.minimums.x {
                 continue;
             }
 
-            if aabb_a.minimums.y >= aabb_b.maximums.y {
+            if a.minimums.y >= b.maximums.y {
                 continue;
             }
-            if aabb_a.maximums.y <= aabb_b.minimums.y {
+            if a.maximums.y <= b.minimums.y {
                 continue;
             }
 
-            if aabb_a.minimums.z >= aabb_b.maximums.z {
+            if a.minimums.z >= b.maximums.z {
                 continue;
             }
-            if aabb_a.maximums.z <= aabb_b.minimums.z {
+            if a.maximums.z <= b.minimums.z {
                 continue;
             }
 
             // Overlap on all three axes, so their intersection must be non-empty
-            broad_contacts.send(BroadContact { a: *a, b: *b });
+            broad_contacts.send(BroadContact {
+                a: a.entity,
+                b: b.entity,
+            });
         }
     }
     // let t2 = Instant::now();
@@ -61,49 +83,136 @@ pub fn broadphase_system(
     // info!("sort {:?}, sweep {:?}", sort, sweep);
 }
 
-// #[cfg(feature = "trace")]
-// let stage_span = bevy_utils::tracing::info_span!("stage", name = "extract");
-// #[cfg(feature = "trace")]
-// let _stage_guard = stage_span.enter();
+pub fn bvh_broadphase_system(
+    mut broad_contacts: EventWriter<BroadContact>,
+    mut bvh: ResMut<Bvh>,
+    query: Query<(Entity, &GlobalAabb)>,
+    synced: Res<BroadphaseSynced>,
+    config: Res<PhysicsConfig>,
+) {
+    if config.broadphase != BroadphaseMode::Bvh {
+        if !bvh.is_empty() {
+            bvh.clear();
+        }
+        return;
+    }
 
-#[allow(dead_code)]
-fn cmp_x_axis(a: &(Entity, &GlobalAabb), b: &(Entity, &GlobalAabb)) -> std::cmp::Ordering {
-    // Sort on minimum value along either x, y, or z axis
-    let min_a = a.1.minimums.x;
-    let min_b = b.1.minimums.x;
-    if min_a < min_b {
-        return std::cmp::Ordering::Less;
+    if !synced.0 || bvh.is_empty() {
+        sync_bvh(&mut bvh, &query);
     }
-    if min_a > min_b {
-        return std::cmp::Ordering::Greater;
+    bvh.for_each_pair(|a, b| broad_contacts.send(BroadContact { a, b }));
+}
+
+pub fn grid_broadphase_system(
+    mut broad_contacts: EventWriter<BroadContact>,
+    mut grid: ResMut<SpatialHashGrid>,
+    query: Query<(Entity, &GlobalAabb)>,
+    synced: Res<BroadphaseSynced>,
+    config: Res<PhysicsConfig>,
+) {
+    if config.broadphase != BroadphaseMode::SpatialHash {
+        if !grid.is_empty() {
+            grid.clear();
+        }
+        return;
+    }
+
+    if !synced.0 || grid.is_empty() {
+        sync_grid(&mut grid, &query, config.grid_cell_size);
     }
-    std::cmp::Ordering::Equal
+    grid.for_each_pair(|a, b| broad_contacts.send(BroadContact { a, b }));
 }
 
-#[allow(dead_code)]
-fn cmp_y_axis(a: &(Entity, &GlobalAabb), b: &(Entity, &GlobalAabb)) -> std::cmp::Ordering {
-    // Sort on minimum value along either x, y, or z axis
-    let min_a = a.1.minimums.y;
-    let min_b = b.1.minimums.y;
-    if min_a < min_b {
-        return std::cmp::Ordering::Less;
+/// Brings the active broad phase up to date with where bodies ended up, scene queries read it
+/// between steps and while physics is paused
+pub fn sync_broadphase_system(
+    mut sap: ResMut<SweepAndPrune>,
+    mut bvh: ResMut<Bvh>,
+    mut grid: ResMut<SpatialHashGrid>,
+    query: Query<(Entity, &GlobalAabb)>,
+    added: Query<Entity, Added<GlobalAabb>>,
+    mut synced: ResMut<BroadphaseSynced>,
+    config: Res<PhysicsConfig>,
+) {
+    // While paused nothing moves, so most frames there is nothing to do
+    let resync = !synced.0;
+    if config.broadphase == BroadphaseMode::SweepAndPrune {
+        if resync || sap.is_empty() {
+            sync_sweep_and_prune(&mut sap, &query, &added);
+        }
+    } else if !sap.is_empty() {
+        sap.clear();
     }
-    if min_a > min_b {
-        return std::cmp::Ordering::Greater;
+    if config.broadphase == BroadphaseMode::Bvh {
+        if resync || bvh.is_empty() {
+            sync_bvh(&mut bvh, &query);
+        }
+    } else if !bvh.is_empty() {
+        bvh.clear();
+    }
+    if config.broadphase == BroadphaseMode::SpatialHash {
+        if resync || grid.is_empty() {
+            sync_grid(&mut grid, &query, config.grid_cell_size);
+        }
+    } else if !grid.is_empty() {
+        grid.clear();
     }
-    std::cmp::Ordering::Equal
+    synced.0 = true;
 }
 
-#[allow(dead_code)]
-fn cmp_z_axis(a: &(Entity, GlobalAabb), b: &(Entity, GlobalAabb)) -> std::cmp::Ordering {
-    // Sort on minimum value along either x, y, or z axis
-    let min_a = a.1.minimums.z;
-    let min_b = b.1.minimums.z;
-    if min_a < min_b {
-        return std::cmp::Ordering::Less;
+fn sync_sweep_and_prune(
+    sap: &mut SweepAndPrune,
+    query: &Query<(Entity, &GlobalAabb)>,
+    added: &Query<Entity, Added<GlobalAabb>>,
+) {
+    // The list is kept from last frame, so only new entities need adding
+    let mut added_count = 0;
+    if sap.is_empty() {
+        for (entity, _) in query.iter() {
+            sap.insert(entity);
+            added_count += 1;
+        }
+    } else {
+        for entity in added.iter() {
+            if sap.insert(entity) {
+                added_count += 1;
+            }
+        }
     }
-    if min_a > min_b {
-        return std::cmp::Ordering::Greater;
+
+    // Despawned entities or ones that lost their bounds are dropped here
+    let axis_changed = sap.refresh(|entity| query.get(entity).ok().map(|(_, aabb)| *aabb));
+
+    // Sort the array on the axis with the most spread
+    // New entries start at the end, too many and insertion sort goes quadratic
+    if axis_changed || added_count > sap.len() / 8 {
+        sap.sort_full();
+    } else {
+        sap.sort();
     }
-    std::cmp::Ordering::Equal
 }
+
+fn sync_bvh(bvh: &mut Bvh, query: &Query<(Entity, &GlobalAabb)>) {
+    // Refit the tree, only leaves that moved past their margin get reinserted
+    for (entity, aabb) in query.iter() {
+        if aabb.is_valid() {
+            bvh.set(entity, aabb.minimums, aabb.maximums);
+        }
+    }
+    bvh.retain(|entity| query.get(entity).is_ok());
+}
+
+fn sync_grid(grid: &mut SpatialHashGrid, query: &Query<(Entity, &GlobalAabb)>, cell_size: Real) {
+    grid.rebuild(
+        cell_size,
+        query
+            .iter()
+            .filter(|(_, aabb)| aabb.is_valid())
+            .map(|(entity, aabb)| (entity, aabb.minimums, aabb.maximums)),
+    );
+}
+
+// #[cfg(feature = "trace")]
+// let stage_span = bevy_utils::tracing::info_span!("stage", name = "extract");
+// #[cfg(feature = "trace")]
+// let _stage_guard = stage_span.enter();
@@ -1,17 +1,36 @@
-use bevy::{
-    math::Vec3,
-    prelude::*,
-};
+use bevy::prelude::*;
 
-use crate::{Body, PhysicsTime};
-
-pub fn dynamics_system(mut query: Query<&mut Body>, pt: Res<PhysicsTime>) {
-    for mut body in query.iter_mut() {
+use crate::{Body, LockedAxes, PhysicsTime, Vector};
 
+pub fn dynamics_system(mut query: Query<(&mut Body, Option<&LockedAxes>)>, pt: Res<PhysicsTime>) {
+    for (mut body, locked) in query.iter_mut() {
         // Apply Gravity, it needs to be an impluse
         let mass = 1.0 / body.inv_mass;
-        let gravey_impluse = Vec3::new(0.0, -10.0, 0.0) * mass * pt.time;
-        body.apply_impulse_linear( gravey_impluse);
+        let gravey_impluse = Vector::new(0.0, -10.0, 0.0) * mass * pt.time;
+        body.apply_impulse_linear(gravey_impluse);
+        if let Some(locked) = locked {
+            locked.lock_velocities(&mut body);
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_util::{run, spawn_ball, test_world},
+        Body, LockedAxes, Vector,
+    };
+
+    use super::dynamics_system;
+
+    #[test]
+    fn locked_axes_ignore_gravity() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        world.entity_mut(a).insert(LockedAxes::TRANSLATION_Y);
+
+        run(&mut world, dynamics_system);
+
+        assert_eq!(world.get::<Body>(a).unwrap().linear_velocity, Vector::ZERO);
+    }
+}
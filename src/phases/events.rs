@@ -0,0 +1,131 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    ordered_pair, CollidingEntities, CollisionEnded, CollisionPairs, CollisionStarted, Contact,
+};
+
+// Contacts are sent every frame while bodies touch, here we diff them against last frame
+// so gameplay code only has to react once
+pub fn collision_events_system(
+    mut contacts: EventReader<Contact>,
+    mut pairs: ResMut<CollisionPairs>,
+    mut colliding: Query<&mut CollidingEntities>,
+    mut started: EventWriter<CollisionStarted>,
+    mut ended: EventWriter<CollisionEnded>,
+) {
+    let mut current = HashSet::default();
+    // Dynamic detection also sends contacts for hits later in the frame,
+    // the solver may stop those from ever happening
+    for contact in contacts
+        .iter()
+        .filter(|c| c.time_of_impact == 0.0 || c.separation_dist <= 0.0)
+    {
+        current.insert(ordered_pair(contact.a, contact.b));
+    }
+
+    for &(a, b) in current.iter() {
+        if pairs.active.contains(&(a, b)) {
+            continue;
+        }
+        if let Ok(mut entities) = colliding.get_mut(a) {
+            entities.insert(b);
+        }
+        if let Ok(mut entities) = colliding.get_mut(b) {
+            entities.insert(a);
+        }
+        started.send(CollisionStarted(a, b));
+    }
+
+    for &(a, b) in pairs.active.iter() {
+        if current.contains(&(a, b)) {
+            continue;
+        }
+        // Either entity may have been despawned since, so dont unwrap here
+        if let Ok(mut entities) = colliding.get_mut(a) {
+            entities.remove(b);
+        }
+        if let Ok(mut entities) = colliding.get_mut(b) {
+            entities.remove(a);
+        }
+        ended.send(CollisionEnded(a, b));
+    }
+
+    pairs.active = current;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        test_util::{contact, run, spawn_ball, test_world},
+        CollidingEntities, CollisionEnded, CollisionStarted, Contact, Vector,
+    };
+
+    use super::collision_events_system;
+
+    // Runs one frame and counts the started and ended events it sent
+    fn step(world: &mut World, touching: Option<Contact>) -> (usize, usize) {
+        world
+            .get_resource_mut::<Events<CollisionStarted>>()
+            .unwrap()
+            .clear();
+        world
+            .get_resource_mut::<Events<CollisionEnded>>()
+            .unwrap()
+            .clear();
+        {
+            let mut contacts = world.get_resource_mut::<Events<Contact>>().unwrap();
+            contacts.clear();
+            if let Some(touching) = touching {
+                contacts.send(touching);
+            }
+        }
+
+        run(world, collision_events_system);
+
+        let started = world.get_resource::<Events<CollisionStarted>>().unwrap();
+        let ended = world.get_resource::<Events<CollisionEnded>>().unwrap();
+        (
+            started.get_reader().iter(started).count(),
+            ended.get_reader().iter(ended).count(),
+        )
+    }
+
+    #[test]
+    fn collision_started_and_ended_sent_once() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.entity_mut(a).insert(CollidingEntities::default());
+        world.entity_mut(b).insert(CollidingEntities::default());
+
+        assert_eq!(step(&mut world, Some(contact(a, b))), (1, 0));
+        assert!(world.get::<CollidingEntities>(a).unwrap().contains(b));
+        assert!(world.get::<CollidingEntities>(b).unwrap().contains(a));
+
+        // Still touching, and the pair order doesnt matter
+        assert_eq!(step(&mut world, Some(contact(b, a))), (0, 0));
+
+        assert_eq!(step(&mut world, None), (0, 1));
+        assert!(world.get::<CollidingEntities>(a).unwrap().is_empty());
+        assert!(world.get::<CollidingEntities>(b).unwrap().is_empty());
+    }
+
+    #[test]
+    fn speculative_contacts_are_not_touching() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X * 3.0);
+        world.entity_mut(a).insert(CollidingEntities::default());
+
+        // Would hit halfway through the frame, but hasnt yet
+        let speculative = Contact {
+            separation_dist: 1.0,
+            time_of_impact: 0.5,
+            ..contact(a, b)
+        };
+        assert_eq!(step(&mut world, Some(speculative)), (0, 0));
+        assert!(world.get::<CollidingEntities>(a).unwrap().is_empty());
+    }
+}
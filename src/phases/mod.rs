@@ -1,11 +1,13 @@
+mod broad;
 mod dynamics;
+mod events;
 mod narrow;
 mod resolve;
 mod update;
-mod broad;
 
+pub use broad::*;
 pub use dynamics::*;
+pub use events::*;
 pub use narrow::*;
 pub use resolve::*;
 pub use update::*;
-pub use broad::*;
\ No newline at end of file
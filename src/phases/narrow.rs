@@ -1,103 +1,276 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, tasks::ComputeTaskPool};
 
 use crate::{
     sphere_sphere_dynamic, Body, BroadContact, ColliderSphere, ColliderType, CollisionDetection,
-    Contact, PhysicsConfig, PhysicsTime,
+    Contact, ContactHooks, LockedAxes, PhysicsConfig, PhysicsTime, Position, Real, Vector,
 };
 
+// Below this many pairs per task, spawning tasks costs more than it saves
+const MIN_PAIRS_PER_TASK: usize = 64;
+
+#[allow(clippy::too_many_arguments)]
 pub fn narrow_system(
-    query: Query<(&mut GlobalTransform, &mut Body, &ColliderType)>,
+    query: Query<(&Position, &Body, &ColliderType)>,
     spheres: Query<&ColliderSphere>,
     mut broad_contacts: EventReader<BroadContact>,
     mut contacts: EventWriter<Contact>,
     config: Res<PhysicsConfig>,
+    hooks: Res<ContactHooks>,
     pt: Res<PhysicsTime>,
+    pool: Res<ComputeTaskPool>,
 ) {
-    // let mut pairs = query.iter_combinations_mut();
-    // while let Some([(a, mut trans_a, mut body_a, type_a), (b, mut trans_b, mut body_b, type_b)]) =
-    //     pairs.fetch_next()
-    // {
-    for pair in broad_contacts.iter() {
-        unsafe {
-            let (mut trans_a, mut body_a, type_a) = query.get_unchecked(pair.a).unwrap();
-            let (mut trans_b, mut body_b, type_b) = query.get_unchecked(pair.b).unwrap();
-            match (type_a, type_b) {
-                (ColliderType::Sphere, ColliderType::Sphere) => {
-                    let sphere_a = spheres.get(pair.a).unwrap();
-                    let sphere_b = spheres.get(pair.b).unwrap();
-
-                    match config.detection {
-                        CollisionDetection::Static => {
-                            let ab = trans_b.translation - trans_a.translation;
-                            let radius_ab = sphere_a.radius + sphere_b.radius;
-                            let radius_ab_sq = radius_ab * radius_ab;
-                            let ab_len_sq = ab.length_squared();
-                            if ab_len_sq <= radius_ab_sq {
-                                let normal = ab.normalize();
-                                let ab = trans_a.translation - trans_b.translation;
-                                let separation_dist =
-                                    ab.length() - (sphere_a.radius + sphere_b.radius);
-
-                                // convert world space contacts to local space
-                                contacts.send(Contact {
-                                    a: pair.a,
-                                    b: pair.b,
-                                    world_point_a: trans_a.translation + (normal * sphere_a.radius),
-                                    world_point_b: trans_b.translation - (normal * sphere_b.radius),
-                                    normal,
-                                    local_point_a: Vec3::ZERO,
-                                    local_point_b: Vec3::ZERO,
-                                    separation_dist,
-                                    time_of_impact: 0.0,
-                                });
-                            }
-                        }
-                        CollisionDetection::Dynamic => {
-                            if let Some((world_point_a, world_point_b, time_of_impact)) =
-                                sphere_sphere_dynamic(
-                                    sphere_a.radius,
-                                    sphere_b.radius,
-                                    &body_a,
-                                    &body_b,
-                                    pt.time,
-                                )
-                            {
-                                // step bodies forward to get local space collision points
-                                body_a.update(&mut trans_a, time_of_impact);
-                                body_b.update(&mut trans_b, time_of_impact);
-
-                                // convert world space contacts to local space
-                                let local_point_a = body_a.world_to_local(&trans_a, world_point_a);
-                                let local_point_b = body_b.world_to_local(&trans_b, world_point_b);
-
-                                let normal =
-                                    (trans_a.translation - trans_b.translation).normalize();
-
-                                // unwind time step
-                                body_a.update(&mut trans_a, -time_of_impact);
-                                body_b.update(&mut trans_b, -time_of_impact);
-
-                                // calculate the separation distance
-                                let ab = trans_a.translation - trans_b.translation;
-                                let separation_dist =
-                                    ab.length() - (sphere_a.radius + sphere_b.radius);
-
-                                contacts.send(Contact {
-                                    a: pair.a,
-                                    b: pair.b,
-                                    world_point_a,
-                                    world_point_b,
-                                    local_point_a,
-                                    local_point_b,
-                                    normal,
-                                    separation_dist,
-                                    time_of_impact,
-                                });
-                            }
-                        }
+    let pairs = broad_contacts.iter().collect::<Vec<_>>();
+    if pairs.is_empty() {
+        return;
+    }
+
+    // Detection only reads bodies, so pairs can be split across threads,
+    // each task fills its own buffer
+    let chunk_size = (pairs.len() / pool.thread_num().max(1)).max(MIN_PAIRS_PER_TASK);
+    let query = &query;
+    let spheres = &spheres;
+    let config: &PhysicsConfig = &config;
+    let hooks: &ContactHooks = &hooks;
+    let dt = pt.time;
+    let buffers = pool.scope(|scope| {
+        for chunk in pairs.chunks(chunk_size) {
+            scope.spawn(async move {
+                chunk
+                    .iter()
+                    .filter_map(|pair| narrow_pair(pair, query, spheres, config, hooks, dt))
+                    .collect::<Vec<_>>()
+            });
+        }
+    });
+
+    for buffer in buffers {
+        for contact in buffer {
+            contacts.send(contact);
+        }
+    }
+}
+
+fn narrow_pair(
+    pair: &BroadContact,
+    query: &Query<(&Position, &Body, &ColliderType)>,
+    spheres: &Query<&ColliderSphere>,
+    config: &PhysicsConfig,
+    hooks: &ContactHooks,
+    dt: Real,
+) -> Option<Contact> {
+    // A body never collides with itself
+    if pair.a == pair.b {
+        return None;
+    }
+    // Either entity may have been despawned or lost its body since the broad phase
+    let (p_a, body_a, type_a) = query.get(pair.a).ok()?;
+    let (p_b, body_b, type_b) = query.get(pair.b).ok()?;
+    let position_a = p_a.translation;
+    let position_b = p_b.translation;
+    let contact = match (type_a, type_b) {
+        (ColliderType::Sphere, ColliderType::Sphere) => {
+            let sphere_a = spheres.get(pair.a).ok()?;
+            let sphere_b = spheres.get(pair.b).ok()?;
+
+            match config.detection {
+                CollisionDetection::Static => {
+                    let ab = position_b - position_a;
+                    let radius_ab = sphere_a.radius + sphere_b.radius;
+                    let radius_ab_sq = radius_ab * radius_ab;
+                    let ab_len_sq = ab.length_squared();
+                    if ab_len_sq <= radius_ab_sq {
+                        let normal = ab.normalize();
+                        let ab = position_a - position_b;
+                        let separation_dist = ab.length() - (sphere_a.radius + sphere_b.radius);
+
+                        // convert world space contacts to local space
+                        Some(Contact {
+                            a: pair.a,
+                            b: pair.b,
+                            world_point_a: position_a + (normal * sphere_a.radius),
+                            world_point_b: position_b - (normal * sphere_b.radius),
+                            normal,
+                            local_point_a: Vector::ZERO,
+                            local_point_b: Vector::ZERO,
+                            separation_dist,
+                            time_of_impact: 0.0,
+                            elasticity: body_a.elasticity * body_b.elasticity,
+                            friction: body_a.friction * body_b.friction,
+                            surface_velocity: Vector::ZERO,
+                        })
+                    } else {
+                        None
+                    }
+                }
+                CollisionDetection::Dynamic => {
+                    if let Some((world_point_a, world_point_b, time_of_impact)) =
+                        sphere_sphere_dynamic(sphere_a.radius, sphere_b.radius, body_a, body_b, dt)
+                    {
+                        // step copies of the bodies forward to get local space collision points,
+                        // the real ones are left alone so other tasks can keep reading them
+                        let mut step_a = body_a.clone();
+                        let mut step_b = body_b.clone();
+                        let mut step_p_a = *p_a;
+                        let mut step_p_b = *p_b;
+                        // Only finds the local points, locked axes make no difference to those
+                        step_a.update(&mut step_p_a, &LockedAxes::empty(), time_of_impact);
+                        step_b.update(&mut step_p_b, &LockedAxes::empty(), time_of_impact);
+
+                        // convert world space contacts to local space
+                        let local_point_a = step_a.world_to_local(&step_p_a, world_point_a);
+                        let local_point_b = step_b.world_to_local(&step_p_b, world_point_b);
+
+                        let normal = (step_p_b.translation - step_p_a.translation).normalize();
+
+                        // calculate the separation distance
+                        let ab = position_a - position_b;
+                        let separation_dist = ab.length() - (sphere_a.radius + sphere_b.radius);
+
+                        Some(Contact {
+                            a: pair.a,
+                            b: pair.b,
+                            world_point_a,
+                            world_point_b,
+                            local_point_a,
+                            local_point_b,
+                            normal,
+                            separation_dist,
+                            time_of_impact,
+                            elasticity: body_a.elasticity * body_b.elasticity,
+                            friction: body_a.friction * body_b.friction,
+                            surface_velocity: Vector::ZERO,
+                        })
+                    } else {
+                        None
                     }
                 }
             }
         }
+    };
+
+    // Give user hooks a chance to edit or discard the contact before its resolved
+    let mut contact = contact?;
+    if hooks.apply(&mut contact, body_a, body_b) {
+        Some(contact)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::system::SystemState,
+        prelude::*,
+        tasks::{ComputeTaskPool, TaskPoolBuilder},
+    };
+
+    use crate::{
+        test_util::{run, spawn_ball, test_world, Rng},
+        Body, BroadContact, ColliderSphere, ColliderType, CollisionDetection, Contact,
+        ContactHooks, PhysicsConfig, PhysicsTime, Position, Vector,
+    };
+
+    use super::{narrow_pair, narrow_system, MIN_PAIRS_PER_TASK};
+
+    #[test]
+    fn parallel_matches_serial() {
+        for detection in [CollisionDetection::Static, CollisionDetection::Dynamic] {
+            let mut world = test_world();
+            // Enough threads that the pairs get split however many cores run the test
+            world.insert_resource(ComputeTaskPool(
+                TaskPoolBuilder::new().num_threads(4).build(),
+            ));
+            world.get_resource_mut::<PhysicsConfig>().unwrap().detection = detection;
+
+            let mut rng = Rng(0xda94_2042_e4dd_58b5);
+            let balls = (0..200)
+                .map(|_| {
+                    let ball =
+                        spawn_ball(&mut world, rng.vector(Vector::ZERO, Vector::splat(12.0)));
+                    world.get_mut::<Body>(ball).unwrap().linear_velocity =
+                        rng.vector(Vector::splat(-30.0), Vector::splat(30.0));
+                    ball
+                })
+                .collect::<Vec<_>>();
+
+            // Every pair that could be close, plenty of them miss
+            let mut pairs = Vec::new();
+            for (i, &a) in balls.iter().enumerate() {
+                for &b in balls.iter().skip(i + 1) {
+                    let position_a = world.get::<Position>(a).unwrap().translation;
+                    let position_b = world.get::<Position>(b).unwrap().translation;
+                    if position_a.distance(position_b) < 4.0 {
+                        pairs.push(BroadContact { a, b });
+                    }
+                }
+            }
+            assert!(pairs.len() > 4 * MIN_PAIRS_PER_TASK);
+
+            // Contact has no PartialEq, its debug output covers every field
+            let mut state: SystemState<(
+                Query<(&Position, &Body, &ColliderType)>,
+                Query<&ColliderSphere>,
+            )> = SystemState::new(&mut world);
+            let serial = {
+                let (query, spheres) = state.get(&world);
+                let config = world.get_resource::<PhysicsConfig>().unwrap();
+                let hooks = world.get_resource::<ContactHooks>().unwrap();
+                let dt = world.get_resource::<PhysicsTime>().unwrap().time;
+                pairs
+                    .iter()
+                    .filter_map(|pair| narrow_pair(pair, &query, &spheres, config, hooks, dt))
+                    .map(|contact| format!("{:?}", contact))
+                    .collect::<Vec<_>>()
+            };
+            assert!(!serial.is_empty());
+
+            {
+                let mut broad_contacts = world.get_resource_mut::<Events<BroadContact>>().unwrap();
+                for pair in pairs {
+                    broad_contacts.send(pair);
+                }
+            }
+            run(&mut world, narrow_system);
+            let contacts = world.get_resource::<Events<Contact>>().unwrap();
+            let parallel = contacts
+                .get_reader()
+                .iter(contacts)
+                .map(|contact| format!("{:?}", contact))
+                .collect::<Vec<_>>();
+            assert_eq!(parallel, serial);
+        }
+    }
+
+    #[test]
+    fn narrow_ignores_self_pairs() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        world
+            .get_resource_mut::<Events<BroadContact>>()
+            .unwrap()
+            .send(BroadContact { a, b: a });
+
+        run(&mut world, narrow_system);
+
+        let contacts = world.get_resource::<Events<Contact>>().unwrap();
+        assert_eq!(contacts.get_reader().iter(contacts).count(), 0);
+    }
+
+    #[test]
+    fn narrow_skips_despawned_bodies() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world
+            .get_resource_mut::<Events<BroadContact>>()
+            .unwrap()
+            .send(BroadContact { a, b });
+        world.despawn(b);
+
+        run(&mut world, narrow_system);
+
+        let contacts = world.get_resource::<Events<Contact>>().unwrap();
+        assert_eq!(contacts.get_reader().iter(contacts).count(), 0);
     }
 }
@@ -1,75 +1,462 @@
-use bevy::prelude::*;
-use crate::{Body, Contact};
+use bevy::{prelude::*, tasks::ComputeTaskPool};
 
+use crate::{
+    color_constraints, push_apart, solve_contact, solve_joint, Body, BreakableJoint, Contact,
+    ContactConstraint, ContactImpulse, ContactImpulseThreshold, Joint, JointBroken,
+    JointConstraint, JointFrame, JointRow, JointState, LockedAxes, PhysicsConfig, PhysicsTime,
+    Position, Real, SolverBodies, SolverBody, SolverBodyState,
+};
+
+// Below this many constraints per task, spawning tasks costs more than it saves
+const MIN_CONSTRAINTS_PER_TASK: usize = 64;
+
+enum Constraint<'a> {
+    Contact(&'a Contact, ContactConstraint),
+    Joint(JointConstraint),
+}
+
+enum Solved {
+    Contact(ContactConstraint),
+    Joint(Vec<JointRow>),
+}
+
+struct ConstraintResult {
+    constraint: usize,
+    a: SolverBodyState,
+    b: SolverBodyState,
+    solved: Solved,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn resolve_system(
+    mut commands: Commands,
     mut contacts: EventReader<Contact>,
-    query: Query<(&mut Body, &mut GlobalTransform)>,
+    joints: Query<(Entity, &Joint, &JointState)>,
+    breakable: Query<&BreakableJoint>,
+    mut broken: EventWriter<JointBroken>,
+    mut query: Query<(&mut Body, &mut Position, Option<&LockedAxes>)>,
+    thresholds: Query<&ContactImpulseThreshold>,
+    mut impulses: EventWriter<ContactImpulse>,
+    config: Res<PhysicsConfig>,
+    pt: Res<PhysicsTime>,
+    pool: Res<ComputeTaskPool>,
 ) {
+    // Copy the bodies out of the world, the solver only works on these copies
+    let mut bodies = SolverBodies::default();
+    let mut constraints = Vec::new();
+    let mut pairs = Vec::new();
     for contact in contacts.iter() {
-        unsafe {
-
-            let (mut body_a, mut trans_a) = query.get_unchecked(contact.a).unwrap();
-            let (mut body_b, mut trans_b) = query.get_unchecked(contact.b).unwrap();
-
-            let elasticity = body_a.elasticity * body_b.elasticity;
-            let total_inv_mass = body_a.inv_mass + body_b.inv_mass;
-        
-            let ra = contact.world_point_a - body_a.center_of_mass_world;
-            let rb = contact.world_point_b - body_b.center_of_mass_world;
-        
-            let angular_j_a = (body_a.inverse_inertia_tensor_world * ra.cross(contact.normal)).cross(ra);
-            let angular_j_b = (body_b.inverse_inertia_tensor_world * rb.cross(contact.normal)).cross(rb);
-            let angular_factor = (angular_j_a + angular_j_b).dot(contact.normal);
-        
-            // Get the world space velocity of the motion and rotation
-            let vel_a = body_a.linear_velocity + body_a.angular_velocity.cross(ra);
-            let vel_b = body_b.linear_velocity + body_b.angular_velocity.cross(rb);
-        
-            // Calculate the collion impulse
-            let vab = vel_a - vel_b;
-            let impluse_j = -(1.0 + elasticity) * vab.dot(contact.normal) / (total_inv_mass + angular_factor);
-            let impluse_vec_j = contact.normal * impluse_j;
-
-            body_a.apply_impulse(contact.world_point_a, impluse_vec_j);
-            body_b.apply_impulse(contact.world_point_b, -impluse_vec_j);
-
-            // Calculate the friction impulse
-            let friction = body_a.friction * body_b.friction;
-
-            // Find the normal direction of the velocity with respoect to the normal of the collison
-            let velocity_normal = contact.normal * contact.normal.dot(vab);
-            let velocity_tangent = vab - velocity_normal;
-        
-            // Get the tangent velocities relative to the other body
-            let relative_velocity_tangent = velocity_tangent.normalize();
-        
-            let inertia_a = (body_a.inverse_inertia_tensor_world * ra.cross(relative_velocity_tangent)).cross(ra);
-            let inertia_b = (body_b.inverse_inertia_tensor_world * rb.cross(relative_velocity_tangent)).cross(rb);
-            let inv_inertia = (inertia_a + inertia_b).dot(relative_velocity_tangent);
-        
-            // calculat the tangential impluse for friction
-            let reduced_mass = 1.0 / (total_inv_mass + inv_inertia);
-            let impluse_friction = velocity_tangent * (reduced_mass * friction);
-        
-            // TODO: Book didnt have this if check, but I was getitng velocity_tangent of zero leading to
-            // a Vec3 Nan when normalized if perfectly lined up on ground
-            if !impluse_friction.is_nan() {
-                 // apply kinetic friction
-                 body_a.apply_impulse(contact.world_point_a, -impluse_friction);
-                 body_b.apply_impulse(contact.world_point_b, impluse_friction);
+        // Self pairs and despawned bodies are dropped here
+        if let Some((a, b)) =
+            bodies.pair_of(contact.a, contact.b, |entity| copy_body(&query, entity))
+        {
+            let constraint = ContactConstraint::new(contact, &bodies.bodies[a], &bodies.bodies[b]);
+            constraints.push(Constraint::Contact(contact, constraint));
+            pairs.push((a, b));
+        }
+    }
+    let mut rows = Vec::new();
+    for (e, joint, state) in joints.iter() {
+        let (a, b) = match bodies.pair_of(joint.body_a, joint.body_b, |entity| {
+            copy_body(&query, entity)
+        }) {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let frame = joint_frame(joint, &bodies.bodies[a], &bodies.bodies[b], pt.time);
+        joint.build_rows(state, &frame, &mut rows);
+        let constraint = JointConstraint::new(
+            e,
+            std::mem::take(&mut rows),
+            &bodies.bodies[a],
+            &bodies.bodies[b],
+        );
+        constraints.push(Constraint::Joint(constraint));
+        pairs.push((a, b));
+    }
+    if constraints.is_empty() {
+        return;
+    }
+
+    // No two constraints in a batch share a body, so a batch can be solved in parallel
+    // and its results applied afterwards
+    let colored = color_constraints(&pairs, &bodies.bodies);
+    for iteration in 0..config.solver_iterations.max(1) {
+        for batch in colored.batches.iter() {
+            let results = solve_batch(
+                batch,
+                iteration,
+                &constraints,
+                &pairs,
+                &bodies.bodies,
+                &pool,
+            );
+            for result in results {
+                apply_result(result, &mut constraints, &pairs, &mut bodies);
+            }
+        }
+
+        // Whatever didnt fit in a color is solved one at a time
+        for &i in colored.serial.iter() {
+            let result = solve_one(i, iteration, &constraints, &pairs, &bodies.bodies);
+            apply_result(result, &mut constraints, &pairs, &mut bodies);
+        }
+    }
+
+    for constraint in constraints.iter() {
+        let (contact, impulse) = match constraint {
+            Constraint::Contact(contact, constraint) => (contact, constraint.applied()),
+            Constraint::Joint(joint) => {
+                break_joint(
+                    joint,
+                    &joints,
+                    &breakable,
+                    &mut commands,
+                    &mut broken,
+                    pt.time,
+                );
+                continue;
             }
+        };
+        // Report the hit, unless its below what either body cares about
+        let threshold = match (thresholds.get(contact.a), thresholds.get(contact.b)) {
+            (Ok(a), Ok(b)) => a.0.min(b.0),
+            (Ok(a), Err(_)) => a.0,
+            (Err(_), Ok(b)) => b.0,
+            (Err(_), Err(_)) => 0.0,
+        };
+        if impulse.normal + impulse.friction >= threshold {
+            impulses.send(ContactImpulse {
+                a: contact.a,
+                b: contact.b,
+                world_point: contact.world_point_a,
+                normal: contact.normal,
+                normal_impulse: impulse.normal,
+                friction_impulse: impulse.friction,
+            });
+        }
+    }
+
+    // Write the results back, static bodies never change
+    for solver_body in bodies.bodies.iter().filter(|b| !b.is_static()) {
+        if let Ok((mut body, mut position, _)) = query.get_mut(solver_body.entity) {
+            body.linear_velocity = solver_body.body.linear_velocity;
+            body.angular_velocity = solver_body.body.angular_velocity;
+            position.translation = solver_body
+                .locked
+                .lock_translation(position.translation, solver_body.translation);
+        }
+    }
+}
+
+fn copy_body(
+    query: &Query<(&mut Body, &mut Position, Option<&LockedAxes>)>,
+    entity: Entity,
+) -> Option<(Body, Position, LockedAxes)> {
+    query.get(entity).ok().map(|(body, position, locked)| {
+        (body.clone(), *position, locked.copied().unwrap_or_default())
+    })
+}
+
+// This frames impulse is already applied, the joint is gone from the next frame on
+fn break_joint(
+    constraint: &JointConstraint,
+    joints: &Query<(Entity, &Joint, &JointState)>,
+    breakable: &Query<&BreakableJoint>,
+    commands: &mut Commands,
+    broken: &mut EventWriter<JointBroken>,
+    dt: Real,
+) {
+    let limits = match breakable.get(constraint.joint) {
+        Ok(limits) => limits,
+        Err(_) => return,
+    };
+    let (force, torque) = constraint.applied_force(dt);
+    if force <= limits.max_force && torque <= limits.max_torque {
+        return;
+    }
+    if let Ok((e, joint, _)) = joints.get(constraint.joint) {
+        commands.entity(e).despawn();
+        broken.send(JointBroken {
+            joint: e,
+            body_a: joint.body_a,
+            body_b: joint.body_b,
+        });
+    }
+}
+
+fn joint_frame(joint: &Joint, a: &SolverBody, b: &SolverBody, dt: Real) -> JointFrame {
+    let anchor_a = a.translation + a.rotation * joint.anchor_a;
+    let anchor_b = b.translation + b.rotation * joint.anchor_b;
+    JointFrame {
+        anchor_a,
+        anchor_b,
+        r_a: anchor_a - a.body.center_of_mass_world,
+        r_b: anchor_b - b.body.center_of_mass_world,
+        rotation_a: a.rotation,
+        rotation_b: b.rotation,
+        dt,
+    }
+}
+
+fn apply_result(
+    result: ConstraintResult,
+    constraints: &mut [Constraint],
+    pairs: &[(usize, usize)],
+    bodies: &mut SolverBodies,
+) {
+    let (a, b) = pairs[result.constraint];
+    bodies.bodies[a].set_state(result.a);
+    bodies.bodies[b].set_state(result.b);
+    match (result.solved, &mut constraints[result.constraint]) {
+        (Solved::Contact(solved), Constraint::Contact(_, constraint)) => *constraint = solved,
+        (Solved::Joint(rows), Constraint::Joint(joint)) => joint.rows = rows,
+        _ => {}
+    }
+}
 
-            // Lets also move our colliding object to just outside of each other
-            if contact.time_of_impact == 0.0 {
-                let a_move_weight = body_a.inv_mass / total_inv_mass;
-                let b_move_weight = body_b.inv_mass / total_inv_mass;
+fn solve_batch(
+    batch: &[usize],
+    iteration: usize,
+    constraints: &[Constraint],
+    pairs: &[(usize, usize)],
+    bodies: &[SolverBody],
+    pool: &ComputeTaskPool,
+) -> Vec<ConstraintResult> {
+    if batch.len() < 2 * MIN_CONSTRAINTS_PER_TASK {
+        return batch
+            .iter()
+            .map(|&i| solve_one(i, iteration, constraints, pairs, bodies))
+            .collect();
+    }
 
-                let distance = contact.world_point_b - contact.world_point_a;
+    let chunk_size = (batch.len() / pool.thread_num().max(1)).max(MIN_CONSTRAINTS_PER_TASK);
+    pool.scope(|scope| {
+        for chunk in batch.chunks(chunk_size) {
+            scope.spawn(async move {
+                chunk
+                    .iter()
+                    .map(|&i| solve_one(i, iteration, constraints, pairs, bodies))
+                    .collect::<Vec<_>>()
+            });
+        }
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
 
-                trans_a.translation += distance * a_move_weight;
-                trans_b.translation -= distance * b_move_weight;
+// Solves on copies of the two bodies so the shared list is only read.
+// Every constraint is solved each iteration, overlaps are only pushed apart on the first
+fn solve_one(
+    i: usize,
+    iteration: usize,
+    constraints: &[Constraint],
+    pairs: &[(usize, usize)],
+    bodies: &[SolverBody],
+) -> ConstraintResult {
+    let (a, b) = pairs[i];
+    let mut body_a = bodies[a].clone();
+    let mut body_b = bodies[b].clone();
+    let solved = match &constraints[i] {
+        Constraint::Contact(contact, constraint) => {
+            let mut constraint = constraint.clone();
+            solve_contact(&mut constraint, &mut body_a, &mut body_b);
+            if iteration == 0 {
+                push_apart(contact, &mut body_a, &mut body_b);
             }
+            Solved::Contact(constraint)
         }
+        Constraint::Joint(joint) => {
+            let mut rows = joint.rows.clone();
+            solve_joint(&mut rows, &mut body_a, &mut body_b);
+            Solved::Joint(rows)
+        }
+    };
+    body_a.lock();
+    body_b.lock();
+    ConstraintResult {
+        constraint: i,
+        a: body_a.state(),
+        b: body_b.state(),
+        solved,
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        narrow_system,
+        test_util::{contact, run, spawn_ball, test_world},
+        Body, BroadContact, CollisionDetection, Contact, ContactImpulse, ContactImpulseThreshold,
+        PhysicsConfig, Real, Vector,
+    };
+
+    use super::resolve_system;
+
+    // a runs into b at 2 units per second, returns the impulses reported for it
+    fn reported_impulses(threshold_a: Option<Real>, threshold_b: Option<Real>) -> Vec<Real> {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().linear_velocity = Vector::X * 2.0;
+        for (e, threshold) in [(a, threshold_a), (b, threshold_b)] {
+            if let Some(threshold) = threshold {
+                world
+                    .entity_mut(e)
+                    .insert(ContactImpulseThreshold(threshold));
+            }
+        }
+        world
+            .get_resource_mut::<Events<Contact>>()
+            .unwrap()
+            .send(contact(a, b));
+
+        run(&mut world, resolve_system);
+
+        let events = world.get_resource::<Events<ContactImpulse>>().unwrap();
+        events
+            .get_reader()
+            .iter(events)
+            .map(|impulse| impulse.total())
+            .collect()
+    }
+
+    #[test]
+    fn contact_impulse_threshold() {
+        // Equal masses with elasticity 0.5 take a 1.5 impulse
+        let impulses = reported_impulses(None, None);
+        assert_eq!(impulses.len(), 1);
+        assert!((impulses[0] - 1.5).abs() < 1e-4);
+
+        assert!(reported_impulses(Some(10.0), None).is_empty());
+        assert_eq!(reported_impulses(Some(1.0), None).len(), 1);
+        // The lower threshold of the two wins
+        assert_eq!(reported_impulses(Some(10.0), Some(1.0)).len(), 1);
+        assert!(reported_impulses(Some(10.0), Some(20.0)).is_empty());
+    }
+
+    #[test]
+    fn contacts_iterate_to_a_common_velocity() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        let c = spawn_ball(&mut world, Vector::X * 2.0);
+        world.get_mut::<Body>(a).unwrap().linear_velocity = Vector::X * 2.0;
+        {
+            let mut contacts = world.get_resource_mut::<Events<Contact>>().unwrap();
+            for (first, second) in [(a, b), (b, c)] {
+                contacts.send(Contact {
+                    elasticity: 0.0,
+                    ..contact(first, second)
+                });
+            }
+        }
+
+        run(&mut world, resolve_system);
+
+        // Solved once, a would still be running into b
+        let velocity = |e| world.get::<Body>(e).unwrap().linear_velocity.x;
+        let (v_a, v_b, v_c) = (velocity(a), velocity(b), velocity(c));
+        assert!(
+            v_a - v_b < 0.01 && v_b - v_c < 0.01,
+            "{} {} {}",
+            v_a,
+            v_b,
+            v_c
+        );
+        assert!((v_a + v_b + v_c - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn friction_limited_by_normal_impulse() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        world.get_mut::<Body>(a).unwrap().linear_velocity = Vector::new(2.0, 4.0, 0.0);
+        world
+            .get_resource_mut::<Events<Contact>>()
+            .unwrap()
+            .send(contact(a, b));
+
+        run(&mut world, resolve_system);
+
+        // Too fast to stop sliding, friction tops out at 0.5 times the 1.5 normal impulse
+        let events = world.get_resource::<Events<ContactImpulse>>().unwrap();
+        let impulse = events.get_reader().iter(events).next().unwrap();
+        assert!((impulse.normal_impulse - 1.5).abs() < 1e-4);
+        assert!((impulse.friction_impulse - 0.75).abs() < 1e-4);
+    }
+
+    #[test]
+    fn dynamic_contacts_push_bodies_apart() {
+        let mut world = test_world();
+        world.get_resource_mut::<PhysicsConfig>().unwrap().detection = CollisionDetection::Dynamic;
+        // A unit gap, a covers three units this frame
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X * 3.0);
+        world.get_mut::<Body>(a).unwrap().linear_velocity = Vector::X * 180.0;
+        world
+            .get_resource_mut::<Events<BroadContact>>()
+            .unwrap()
+            .send(BroadContact { a, b });
+
+        run(&mut world, narrow_system);
+        run(&mut world, resolve_system);
+
+        let velocity = |e| world.get::<Body>(e).unwrap().linear_velocity.x;
+        assert!(velocity(b) - velocity(a) >= -1e-3);
+        let events = world.get_resource::<Events<ContactImpulse>>().unwrap();
+        let impulse = events.get_reader().iter(events).next().unwrap();
+        assert!(impulse.normal_impulse > 0.0);
+    }
+
+    #[test]
+    fn resolve_ignores_self_pairs() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        world
+            .get_resource_mut::<Events<Contact>>()
+            .unwrap()
+            .send(contact(a, a));
+
+        run(&mut world, resolve_system);
+
+        let body = world.get::<Body>(a).unwrap();
+        assert_eq!(body.linear_velocity, Vector::ZERO);
+        assert_eq!(body.angular_velocity, Vector::ZERO);
+    }
+
+    #[test]
+    fn resolve_skips_despawned_bodies() {
+        let mut world = test_world();
+        let a = spawn_ball(&mut world, Vector::ZERO);
+        let b = spawn_ball(&mut world, Vector::X);
+        // c runs up into d, overlapping it by half a unit
+        let c = spawn_ball(&mut world, Vector::Y * 10.0);
+        let d = spawn_ball(&mut world, Vector::Y * 11.5);
+        world.get_mut::<Body>(a).unwrap().linear_velocity = Vector::X * 2.0;
+        world.get_mut::<Body>(c).unwrap().linear_velocity = Vector::Y * 2.0;
+        {
+            let mut contacts = world.get_resource_mut::<Events<Contact>>().unwrap();
+            contacts.send(contact(a, b));
+            contacts.send(Contact {
+                world_point_a: Vector::Y * 11.0,
+                world_point_b: Vector::Y * 10.5,
+                normal: Vector::Y,
+                ..contact(c, d)
+            });
+        }
+        world.despawn(b);
+
+        run(&mut world, resolve_system);
+
+        // The pair that lost a body is dropped, the other still takes the 1.5 impulse
+        let velocity = |e| world.get::<Body>(e).unwrap().linear_velocity;
+        assert_eq!(velocity(a), Vector::X * 2.0);
+        assert!((velocity(c) - Vector::Y * 0.5).length() < 1e-4);
+        assert!((velocity(d) - Vector::Y * 1.5).length() < 1e-4);
+    }
+}
@@ -1,12 +1,118 @@
 use bevy::prelude::*;
 
-use crate::{Body, PhysicsTime};
+use crate::{from_quaternion, from_vector, Body, LockedAxes, PhysicsTime, Position};
 
 pub fn update_body_system(
-    mut query: Query<(&mut GlobalTransform, &mut Body)>,
+    mut query: Query<(&mut Position, &mut Body, Option<&LockedAxes>)>,
     pt: Res<PhysicsTime>,
 ) {
-    for (mut t, mut body) in query.iter_mut() {
-        body.update(&mut t, pt.time);
+    for (mut p, mut body, locked) in query.iter_mut() {
+        let locked = locked.copied().unwrap_or_default();
+        locked.lock_velocities(&mut body);
+        body.update(&mut p, &locked, pt.time);
+    }
+}
+
+/// Copies positions into the transforms for rendering, the simulation never reads them back
+#[allow(clippy::type_complexity)]
+pub fn sync_transform_system(
+    mut query: Query<
+        (
+            &Position,
+            &mut GlobalTransform,
+            Option<&mut Transform>,
+            Option<&Parent>,
+        ),
+        Changed<Position>,
+    >,
+) {
+    for (position, mut global, transform, parent) in query.iter_mut() {
+        let old_global = *global;
+        global.translation = from_vector(position.translation);
+        global.rotation = from_quaternion(position.rotation);
+
+        // Otherwise propagation would put the old transform back next frame
+        let mut transform = match transform {
+            Some(transform) => transform,
+            None => continue,
+        };
+        if parent.is_some() {
+            // The transform is relative to the parent. Propagation made the old global one
+            // from the parent and this transform, so the parent can be taken back out of it
+            let parent_matrix = old_global.compute_matrix() * transform.compute_matrix().inverse();
+            *transform = Transform::from_matrix(parent_matrix.inverse() * global.compute_matrix());
+        } else {
+            transform.translation = global.translation;
+            transform.rotation = global.rotation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        test_util::{run, spawn_ball, test_world},
+        Body, LockedAxes, Matrix, Position, Quaternion, Vector,
+    };
+
+    use super::{sync_transform_system, update_body_system};
+
+    #[test]
+    fn child_transform_stays_relative_to_parent() {
+        let mut world = test_world();
+        let parent = world
+            .spawn()
+            .insert(GlobalTransform::from_xyz(1.0, 0.0, 0.0))
+            .id();
+        // Propagated last frame, the body now moved on to 3 in world space
+        let e = spawn_ball(&mut world, Vector::X * 3.0);
+        world
+            .entity_mut(e)
+            .insert(Parent(parent))
+            .insert(Transform::from_xyz(1.0, 0.0, 0.0))
+            .insert(GlobalTransform::from_xyz(2.0, 0.0, 0.0));
+
+        run(&mut world, sync_transform_system);
+
+        let transform = world.get::<Transform>(e).unwrap();
+        assert!((transform.translation - Vec3::X * 2.0).length() < 1e-5);
+        let global = world.get::<GlobalTransform>(e).unwrap();
+        assert!((global.translation - Vec3::X * 3.0).length() < 1e-5);
+    }
+
+    #[test]
+    fn precession_stays_off_locked_axes() {
+        let mut world = test_world();
+        let e = spawn_ball(&mut world, Vector::ZERO);
+        world
+            .entity_mut(e)
+            .insert(LockedAxes::ROTATION_X | LockedAxes::ROTATION_Y);
+        // Box shaped inertia, tilted so spinning around world Z precesses
+        let start = Quaternion::from_rotation_x(0.5);
+        world.get_mut::<Position>(e).unwrap().rotation = start;
+        {
+            let mut body = world.get_mut::<Body>(e).unwrap();
+            let inertia = Matrix::from_diagonal(Vector::new(1.0, 2.0, 3.0));
+            body.inertia_tensor = inertia;
+            body.inverse_inertia_tensor_local = inertia.inverse();
+            body.angular_velocity = Vector::Z * 5.0;
+        }
+
+        for _ in 0..30 {
+            run(&mut world, update_body_system);
+        }
+
+        let angular_velocity = world.get::<Body>(e).unwrap().angular_velocity;
+        assert_eq!(angular_velocity.x, 0.0);
+        assert_eq!(angular_velocity.y, 0.0);
+        // Only turned around Z since it started
+        let turned = world.get::<Position>(e).unwrap().rotation * start.conjugate();
+        assert!(
+            turned.x.abs() < 1e-5 && turned.y.abs() < 1e-5,
+            "{:?}",
+            turned
+        );
     }
 }
@@ -0,0 +1,146 @@
+mod overlap;
+mod project;
+mod ray;
+mod shape;
+
+pub use overlap::*;
+pub use project::*;
+pub use ray::*;
+pub use shape::*;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    BroadphaseMode, Bvh, ColliderSphere, ColliderType, GlobalAabb, PhysicsConfig, Position,
+    SpatialHashGrid, SweepAndPrune, Vector,
+};
+
+/// Scene queries against the physics world, use it like any other system param.
+/// Candidates come from the broad phase, which is synced at the end of every frame even while paused,
+/// so colliders are found where the last frame left them
+#[derive(SystemParam)]
+pub struct PhysicsQuery<'w, 's> {
+    colliders: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Position,
+            &'static ColliderType,
+            &'static GlobalAabb,
+        ),
+    >,
+    spheres: Query<'w, 's, &'static ColliderSphere>,
+    sap: Res<'w, SweepAndPrune>,
+    bvh: Res<'w, Bvh>,
+    grid: Res<'w, SpatialHashGrid>,
+    config: Res<'w, PhysicsConfig>,
+}
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    /// Calls back with every collider whose bounds overlap the box
+    fn for_each_candidate(
+        &self,
+        minimums: Vector,
+        maximums: Vector,
+        mut callback: impl FnMut(Entity, &Position, &ColliderType, &GlobalAabb),
+    ) {
+        // Unbounded queries, like an infinite ray, gain nothing from the broad phase
+        if !(minimums.is_finite() && maximums.is_finite()) {
+            for (entity, position, collider_type, aabb) in self.colliders.iter() {
+                callback(entity, position, collider_type, aabb);
+            }
+            return;
+        }
+
+        let mut visit = |entity| {
+            if let Ok((entity, position, collider_type, aabb)) = self.colliders.get(entity) {
+                callback(entity, position, collider_type, aabb);
+            }
+        };
+        match self.config.broadphase {
+            BroadphaseMode::SweepAndPrune => self.sap.query_aabb(minimums, maximums, &mut visit),
+            BroadphaseMode::Bvh => self.bvh.query_aabb(minimums, maximums, &mut visit),
+            BroadphaseMode::SpatialHash => self.grid.query_aabb(minimums, maximums, &mut visit),
+        }
+    }
+}
+
+/// Limits which entities a query can return
+#[derive(Default, Clone, Copy)]
+pub struct QueryFilter<'a> {
+    /// Entities to skip, like whoever is casting the ray
+    pub exclude: &'a [Entity],
+    /// Only entities this returns true for are considered
+    pub predicate: Option<&'a dyn Fn(Entity) -> bool>,
+}
+
+impl<'a> QueryFilter<'a> {
+    pub fn exclude(entities: &'a [Entity]) -> Self {
+        QueryFilter {
+            exclude: entities,
+            predicate: None,
+        }
+    }
+
+    pub fn test(&self, entity: Entity) -> bool {
+        if self.exclude.contains(&entity) {
+            return false;
+        }
+        match self.predicate {
+            Some(predicate) => predicate(entity),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        sync_broadphase_system,
+        test_util::{physics_query, run, spawn_ball, test_world},
+        update_aabb, BroadphaseMode, PhysicsConfig, Position, QueryFilter, Vector,
+    };
+
+    // First entity a finite ray from the origin hits
+    fn hit(world: &mut World, direction: Vector) -> Option<Entity> {
+        physics_query(world, |query| {
+            query
+                .cast_ray(Vector::ZERO, direction, 10.0, QueryFilter::default())
+                .map(|hit| hit.entity)
+        })
+    }
+
+    fn sync(world: &mut World) {
+        run(world, update_aabb);
+        run(world, sync_broadphase_system);
+    }
+
+    #[test]
+    fn queries_see_moved_and_new_bodies_while_paused() {
+        for mode in [
+            BroadphaseMode::SweepAndPrune,
+            BroadphaseMode::Bvh,
+            BroadphaseMode::SpatialHash,
+        ] {
+            let mut world = test_world();
+            {
+                let mut config = world.get_resource_mut::<PhysicsConfig>().unwrap();
+                config.enabled = false;
+                config.broadphase = mode;
+            }
+            let ball = spawn_ball(&mut world, Vector::X * 3.0);
+            sync(&mut world);
+            assert_eq!(hit(&mut world, Vector::X), Some(ball), "{:?}", mode);
+
+            world.get_mut::<Position>(ball).unwrap().translation = Vector::Y * 3.0;
+            let new_ball = spawn_ball(&mut world, Vector::Z * 3.0);
+            sync(&mut world);
+            assert_eq!(hit(&mut world, Vector::X), None, "{:?}", mode);
+            assert_eq!(hit(&mut world, Vector::Y), Some(ball), "{:?}", mode);
+            assert_eq!(hit(&mut world, Vector::Z), Some(new_ball), "{:?}", mode);
+        }
+    }
+}
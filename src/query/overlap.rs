@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use crate::{aabb_aabb_intersect, sphere_sphere_static, ColliderType, Position, Vector};
+
+use super::{PhysicsQuery, QueryFilter, QueryShape};
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    /// Every collider containing the point
+    pub fn intersections_with_point(&self, point: Vector, filter: QueryFilter) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        self.for_each_candidate(point, point, |entity, position, collider_type, _| {
+            if !filter.test(entity) {
+                return;
+            }
+            let inside = match collider_type {
+                ColliderType::Sphere => match self.spheres.get(entity) {
+                    Ok(sphere) => {
+                        position.translation.distance_squared(point)
+                            <= sphere.radius * sphere.radius
+                    }
+                    Err(_) => false,
+                },
+            };
+            if inside {
+                entities.push(entity);
+            }
+        });
+        entities
+    }
+
+    /// Every collider whose bounds overlap the box, the colliders shapes are not tested
+    pub fn intersections_with_aabb(
+        &self,
+        minimums: Vector,
+        maximums: Vector,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        self.for_each_candidate(minimums, maximums, |entity, _, _, aabb| {
+            if filter.test(entity)
+                && aabb_aabb_intersect(minimums, maximums, aabb.minimums, aabb.maximums)
+            {
+                entities.push(entity);
+            }
+        });
+        entities
+    }
+
+    /// Every collider overlapping the shape placed at position
+    pub fn intersections_with_shape(
+        &self,
+        shape: QueryShape,
+        position: &Position,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        let center = position.translation;
+        let half_extents = shape.half_extents();
+
+        let mut entities = Vec::new();
+        self.for_each_candidate(
+            center - half_extents,
+            center + half_extents,
+            |entity, other, collider_type, _| {
+                if !filter.test(entity) {
+                    return;
+                }
+                let overlaps = match (shape, collider_type) {
+                    (QueryShape::Sphere { radius }, ColliderType::Sphere) => {
+                        match self.spheres.get(entity) {
+                            Ok(sphere) => sphere_sphere_static(
+                                radius,
+                                sphere.radius,
+                                center,
+                                other.translation,
+                            )
+                            .is_some(),
+                            Err(_) => false,
+                        }
+                    }
+                };
+                if overlaps {
+                    entities.push(entity);
+                }
+            },
+        );
+        entities
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{sphere_project_point, sphere_sphere_distance, ColliderType, Position, Real, Vector};
+
+use super::{PhysicsQuery, QueryFilter};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointProjection {
+    pub entity: Entity,
+    /// Closest point on the collider
+    pub point: Vector,
+    pub is_inside: bool,
+}
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    /// Finds the collider closest to point and the point on it. With solid a point inside
+    /// a collider projects onto itself, otherwise onto the colliders surface
+    pub fn project_point(
+        &self,
+        point: Vector,
+        solid: bool,
+        filter: QueryFilter,
+    ) -> Option<PointProjection> {
+        let mut closest: Option<(Real, PointProjection)> = None;
+        for (entity, position, collider_type, aabb) in self.colliders.iter() {
+            // Skip anything whose box is already further away than the best so far
+            let to_box = (aabb.minimums - point)
+                .max(point - aabb.maximums)
+                .max(Vector::ZERO);
+            if let Some((best, _)) = closest {
+                if to_box.length_squared() > best {
+                    continue;
+                }
+            }
+            if !filter.test(entity) {
+                continue;
+            }
+
+            let (projected, is_inside) = match collider_type {
+                ColliderType::Sphere => match self.spheres.get(entity) {
+                    Ok(sphere) => {
+                        sphere_project_point(position.translation, sphere.radius, point, solid)
+                    }
+                    Err(_) => continue,
+                },
+            };
+            let dist_sq = projected.distance_squared(point);
+            if closest.map_or(true, |(best, _)| dist_sq < best) {
+                closest = Some((
+                    dist_sq,
+                    PointProjection {
+                        entity,
+                        point: projected,
+                        is_inside,
+                    },
+                ));
+            }
+        }
+        closest.map(|(_, projection)| projection)
+    }
+
+    /// Gap between two colliders, zero if they overlap. None if either isnt a collider
+    pub fn distance(&self, a: Entity, b: Entity) -> Option<Real> {
+        let (_, position_a, type_a, _) = self.colliders.get(a).ok()?;
+        let (_, position_b, type_b, _) = self.colliders.get(b).ok()?;
+        match (type_a, type_b) {
+            (ColliderType::Sphere, ColliderType::Sphere) => {
+                let sphere_a = self.spheres.get(a).ok()?;
+                let sphere_b = self.spheres.get(b).ok()?;
+                Some(sphere_sphere_distance(
+                    sphere_a.radius,
+                    sphere_b.radius,
+                    position_a.translation,
+                    position_b.translation,
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_util::{physics_query, spawn_ball, test_world},
+        QueryFilter, Vector,
+    };
+
+    #[test]
+    fn project_point_onto_closest_collider() {
+        let mut world = test_world();
+        let near = spawn_ball(&mut world, Vector::X * 3.0);
+        let far = spawn_ball(&mut world, Vector::X * -5.0);
+
+        physics_query(&mut world, |query| {
+            let projection = query
+                .project_point(Vector::ZERO, true, QueryFilter::default())
+                .unwrap();
+            assert_eq!(projection.entity, near);
+            assert!((projection.point - Vector::X * 2.0).length() < 1e-5);
+            assert!(!projection.is_inside);
+
+            // Inside a solid collider the point is already on it
+            let inside = Vector::X * 3.5;
+            let projection = query
+                .project_point(inside, true, QueryFilter::default())
+                .unwrap();
+            assert!(projection.is_inside);
+            assert_eq!(projection.point, inside);
+            let projection = query
+                .project_point(inside, false, QueryFilter::default())
+                .unwrap();
+            assert!(projection.is_inside);
+            assert!((projection.point - Vector::X * 4.0).length() < 1e-5);
+
+            let projection = query
+                .project_point(Vector::ZERO, true, QueryFilter::exclude(&[near]))
+                .unwrap();
+            assert_eq!(projection.entity, far);
+            assert!((projection.point + Vector::X * 4.0).length() < 1e-5);
+
+            assert_eq!(query.distance(near, far), Some(6.0));
+        });
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use crate::{
+    ray_aabb_intersect, ray_sphere_intersect, ColliderType, GlobalAabb, Position, Real, Vector,
+};
+
+use super::{PhysicsQuery, QueryFilter};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub entity: Entity,
+    /// Hit is at origin + direction * toi
+    pub toi: Real,
+    pub point: Vector,
+    /// Surface normal of the hit collider, pointing out of it
+    pub normal: Vector,
+}
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    /// Returns the closest hit along the ray, toi and max_toi are in units of direction.
+    /// A ray starting inside a collider hits it at toi 0
+    pub fn cast_ray(
+        &self,
+        origin: Vector,
+        direction: Vector,
+        max_toi: Real,
+        filter: QueryFilter,
+    ) -> Option<RayHit> {
+        let end = origin + direction * max_toi;
+        let mut closest: Option<RayHit> = None;
+        self.for_each_candidate(
+            origin.min(end),
+            origin.max(end),
+            |entity, position, collider_type, aabb| {
+                let max_toi = closest.map_or(max_toi, |hit| hit.toi);
+                if let Some(hit) = self.ray_collider(
+                    entity,
+                    position,
+                    collider_type,
+                    aabb,
+                    origin,
+                    direction,
+                    max_toi,
+                    &filter,
+                ) {
+                    closest = Some(hit);
+                }
+            },
+        );
+        closest
+    }
+
+    /// Returns every hit along the ray, sorted closest first
+    pub fn cast_ray_all(
+        &self,
+        origin: Vector,
+        direction: Vector,
+        max_toi: Real,
+        filter: QueryFilter,
+    ) -> Vec<RayHit> {
+        let end = origin + direction * max_toi;
+        let mut hits = Vec::new();
+        self.for_each_candidate(
+            origin.min(end),
+            origin.max(end),
+            |entity, position, collider_type, aabb| {
+                if let Some(hit) = self.ray_collider(
+                    entity,
+                    position,
+                    collider_type,
+                    aabb,
+                    origin,
+                    direction,
+                    max_toi,
+                    &filter,
+                ) {
+                    hits.push(hit);
+                }
+            },
+        );
+        hits.sort_unstable_by(|a, b| a.toi.total_cmp(&b.toi));
+        hits
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn ray_collider(
+        &self,
+        entity: Entity,
+        position: &Position,
+        collider_type: &ColliderType,
+        aabb: &GlobalAabb,
+        origin: Vector,
+        direction: Vector,
+        max_toi: Real,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        // Cheap box test first
+        let (enter, exit) = ray_aabb_intersect(origin, direction, aabb.minimums, aabb.maximums)?;
+        if exit < 0.0 || enter > max_toi || !filter.test(entity) {
+            return None;
+        }
+
+        match collider_type {
+            ColliderType::Sphere => {
+                let sphere = self.spheres.get(entity).ok()?;
+                let (t1, t2) =
+                    ray_sphere_intersect(origin, direction, position.translation, sphere.radius)?;
+                // A zero direction or a degenerate sphere gives NaN, max would turn that into 0
+                if !(t1.is_finite() && t2.is_finite()) {
+                    return None;
+                }
+                // Sphere is behind the ray
+                if t2 < 0.0 {
+                    return None;
+                }
+                let toi = t1.max(0.0);
+                if toi > max_toi {
+                    return None;
+                }
+                let point = origin + direction * toi;
+                Some(RayHit {
+                    entity,
+                    toi,
+                    point,
+                    normal: (point - position.translation).normalize_or_zero(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_util::{physics_query, spawn_ball, test_world},
+        QueryFilter, Real, Vector,
+    };
+
+    #[test]
+    fn cast_ray_all_sorted_closest_first() {
+        let mut world = test_world();
+        let far = spawn_ball(&mut world, Vector::X * 9.0);
+        let near = spawn_ball(&mut world, Vector::X * 3.0);
+        let middle = spawn_ball(&mut world, Vector::X * 6.0);
+
+        let hits = physics_query(&mut world, |query| {
+            query.cast_ray_all(
+                Vector::ZERO,
+                Vector::X,
+                Real::INFINITY,
+                QueryFilter::default(),
+            )
+        });
+        let hits: Vec<_> = hits.iter().map(|hit| (hit.entity, hit.toi)).collect();
+        assert_eq!(hits, vec![(near, 2.0), (middle, 5.0), (far, 8.0)]);
+    }
+
+    #[test]
+    fn zero_direction_hits_nothing() {
+        let mut world = test_world();
+        spawn_ball(&mut world, Vector::X * 3.0);
+        spawn_ball(&mut world, Vector::X * 6.0);
+
+        let hits = physics_query(&mut world, |query| {
+            query.cast_ray_all(
+                Vector::ZERO,
+                Vector::ZERO,
+                Real::INFINITY,
+                QueryFilter::default(),
+            )
+        });
+        assert!(hits.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use crate::{sphere_sphere_sweep, ColliderSphere, ColliderType, Position, Real, Vector};
+
+use super::{PhysicsQuery, QueryFilter};
+
+/// Shapes that can be swept through the world
+#[derive(Debug, Clone, Copy)]
+pub enum QueryShape {
+    Sphere { radius: Real },
+}
+
+impl QueryShape {
+    /// Half size of the box around the shape
+    pub(crate) fn half_extents(&self) -> Vector {
+        match self {
+            QueryShape::Sphere { radius } => Vector::splat(*radius),
+        }
+    }
+}
+
+impl From<&ColliderSphere> for QueryShape {
+    fn from(sphere: &ColliderSphere) -> Self {
+        QueryShape::Sphere {
+            radius: sphere.radius,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShapeHit {
+    pub entity: Entity,
+    /// The shape touches at start + velocity * toi
+    pub toi: Real,
+    /// Contact point on the hit collider
+    pub point: Vector,
+    /// Surface normal of the hit collider, pointing out of it
+    pub normal: Vector,
+}
+
+impl<'w, 's> PhysicsQuery<'w, 's> {
+    /// Sweeps the shape along velocity for up to max_toi and returns the first collider it touches.
+    /// A shape starting in contact hits at toi 0
+    pub fn cast_shape(
+        &self,
+        shape: QueryShape,
+        start_position: &Position,
+        velocity: Vector,
+        max_toi: Real,
+        filter: QueryFilter,
+    ) -> Option<ShapeHit> {
+        let start = start_position.translation;
+        let end = start + velocity * max_toi;
+        let half_extents = shape.half_extents();
+        let sweep_min = start.min(end) - half_extents;
+        let sweep_max = start.max(end) + half_extents;
+
+        let mut closest: Option<ShapeHit> = None;
+        self.for_each_candidate(
+            sweep_min,
+            sweep_max,
+            |entity, position, collider_type, _| {
+                if !filter.test(entity) {
+                    return;
+                }
+
+                let max_toi = closest.map_or(max_toi, |hit| hit.toi);
+                let hit = match (shape, collider_type) {
+                    (QueryShape::Sphere { radius }, ColliderType::Sphere) => {
+                        let sphere = match self.spheres.get(entity) {
+                            Ok(sphere) => sphere,
+                            Err(_) => return,
+                        };
+                        sphere_sphere_sweep(
+                            radius,
+                            sphere.radius,
+                            start,
+                            position.translation,
+                            velocity,
+                            Vector::ZERO,
+                            max_toi,
+                        )
+                        .map(|(_, point, toi)| ShapeHit {
+                            entity,
+                            toi,
+                            point,
+                            normal: (start + velocity * toi - position.translation)
+                                .normalize_or_zero(),
+                        })
+                    }
+                };
+                if hit.is_some() {
+                    closest = hit;
+                }
+            },
+        );
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_broadphase_system,
+        test_util::{physics_query, run, spawn_ball, test_world},
+        Position, QueryFilter, Vector,
+    };
+
+    use super::QueryShape;
+
+    #[test]
+    fn cast_shape_returns_closest_touch() {
+        let mut world = test_world();
+        spawn_ball(&mut world, Vector::X * 12.0);
+        let near = spawn_ball(&mut world, Vector::X * 6.0);
+        // Off to the side of the sweep
+        spawn_ball(&mut world, Vector::new(3.0, 5.0, 0.0));
+        run(&mut world, sync_broadphase_system);
+
+        let cast = |world: &mut _, max_toi, exclude: &[_]| {
+            physics_query(world, |query| {
+                query.cast_shape(
+                    QueryShape::Sphere { radius: 1.0 },
+                    &Position::default(),
+                    Vector::X,
+                    max_toi,
+                    QueryFilter::exclude(exclude),
+                )
+            })
+        };
+
+        let hit = cast(&mut world, 20.0, &[]).unwrap();
+        assert_eq!(hit.entity, near);
+        assert!((hit.toi - 4.0).abs() < 1e-4);
+        assert!((hit.point - Vector::X * 5.0).length() < 1e-4);
+        assert!((hit.normal + Vector::X).length() < 1e-4);
+
+        assert!(cast(&mut world, 3.0, &[]).is_none());
+        let hit = cast(&mut world, 20.0, &[near]).unwrap();
+        assert!((hit.toi - 10.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{Contact, Real, Vector};
+
+use super::{JointRow, SolverBody};
+
+/// Impulse magnitudes applied for one contact
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppliedImpulse {
+    pub normal: Real,
+    pub friction: Real,
+}
+
+/// Rows of one contact for this frame, solved every iteration like a joints rows.
+/// The normal row only pushes, friction is kept within friction times the normal impulse
+#[derive(Debug, Clone)]
+pub struct ContactConstraint {
+    normal: JointRow,
+    tangents: [JointRow; 2],
+    friction: Real,
+}
+
+impl ContactConstraint {
+    pub fn new(contact: &Contact, a: &SolverBody, b: &SolverBody) -> Self {
+        let r_a = contact.world_point_a - a.body.center_of_mass_world;
+        let r_b = contact.world_point_b - b.body.center_of_mass_world;
+
+        // Bounce back at elasticity times the speed they came together at
+        let mut normal = JointRow::linear(contact.normal, r_a, r_b);
+        let approach = normal.velocity(&a.body, &b.body).min(0.0);
+        normal = normal
+            .with_target_velocity(-contact.elasticity * approach)
+            .with_limits(0.0, Real::INFINITY);
+        normal.prepare(&a.body, &b.body);
+
+        // Friction works against b's surface velocity, so a conveyor drags a along with it
+        let (t1, t2) = tangents(contact.normal);
+        let tangents = [t1, t2].map(|tangent| {
+            let mut row = JointRow::linear(tangent, r_a, r_b)
+                .with_target_velocity(-contact.surface_velocity.dot(tangent));
+            row.prepare(&a.body, &b.body);
+            row
+        });
+
+        ContactConstraint {
+            normal,
+            tangents,
+            friction: contact.friction,
+        }
+    }
+
+    /// Total impulse applied so far this frame
+    pub fn applied(&self) -> AppliedImpulse {
+        let friction = Vector::new(self.tangents[0].impulse(), self.tangents[1].impulse(), 0.0);
+        AppliedImpulse {
+            normal: self.normal.impulse(),
+            friction: friction.length(),
+        }
+    }
+}
+
+/// Runs one iteration over the contacts rows
+pub fn solve_contact(constraint: &mut ContactConstraint, a: &mut SolverBody, b: &mut SolverBody) {
+    constraint.normal.solve(&mut a.body, &mut b.body);
+
+    // Friction can only hold as hard as the contact is pushing so far
+    let max_friction = constraint.friction * constraint.normal.impulse();
+    for row in constraint.tangents.iter_mut() {
+        row.min_impulse = -max_friction;
+        row.max_impulse = max_friction;
+        row.solve(&mut a.body, &mut b.body);
+    }
+}
+
+/// Moves overlapping bodies to just outside of each other, its a position fix so once a frame is enough
+pub fn push_apart(contact: &Contact, a: &mut SolverBody, b: &mut SolverBody) {
+    if contact.time_of_impact != 0.0 {
+        return;
+    }
+    let total_inv_mass = a.body.inv_mass + b.body.inv_mass;
+    if total_inv_mass == 0.0 {
+        return;
+    }
+    let a_move_weight = a.body.inv_mass / total_inv_mass;
+    let b_move_weight = b.body.inv_mass / total_inv_mass;
+
+    let distance = contact.world_point_b - contact.world_point_a;
+
+    a.translation += distance * a_move_weight;
+    b.translation -= distance * b_move_weight;
+}
+
+// Two directions perpendicular to the normal and each other
+fn tangents(normal: Vector) -> (Vector, Vector) {
+    let other = if normal.x.abs() < 0.57 {
+        Vector::X
+    } else {
+        Vector::Y
+    };
+    let t1 = normal.cross(other).normalize();
+    (t1, normal.cross(t1))
+}
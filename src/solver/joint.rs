@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+
+use crate::{Body, Real, Vector};
+
+use super::SolverBody;
+
+// How much of the position error is fed back into the velocity each step
+const JOINT_BIAS_FACTOR: Real = 0.2;
+
+/// One scalar velocity constraint between two bodies, the solver drives
+/// J·v + bias towards zero while keeping the total impulse within the limits
+#[derive(Debug, Clone, Copy)]
+pub struct JointRow {
+    pub linear_a: Vector,
+    pub angular_a: Vector,
+    pub linear_b: Vector,
+    pub angular_b: Vector,
+    pub bias: Real,
+    /// Lets the row give a little like a spring, 0 is rigid
+    pub softness: Real,
+    pub min_impulse: Real,
+    pub max_impulse: Real,
+    effective_mass: Real,
+    accumulated: Real,
+}
+
+impl Default for JointRow {
+    fn default() -> Self {
+        JointRow {
+            linear_a: Vector::ZERO,
+            angular_a: Vector::ZERO,
+            linear_b: Vector::ZERO,
+            angular_b: Vector::ZERO,
+            bias: 0.0,
+            softness: 0.0,
+            min_impulse: Real::NEG_INFINITY,
+            max_impulse: Real::INFINITY,
+            effective_mass: 0.0,
+            accumulated: 0.0,
+        }
+    }
+}
+
+impl JointRow {
+    /// Relative velocity of the anchors along the axis, r are the anchors relative to the centers of mass
+    pub fn linear(axis: Vector, r_a: Vector, r_b: Vector) -> Self {
+        JointRow {
+            linear_a: -axis,
+            angular_a: -r_a.cross(axis),
+            linear_b: axis,
+            angular_b: r_b.cross(axis),
+            ..Default::default()
+        }
+    }
+
+    /// Relative angular velocity around the axis
+    pub fn angular(axis: Vector) -> Self {
+        JointRow {
+            angular_a: -axis,
+            angular_b: axis,
+            ..Default::default()
+        }
+    }
+
+    /// Pushes the bodies back towards zero error over the next few steps
+    pub fn with_position_error(mut self, error: Real, dt: Real) -> Self {
+        if dt > 0.0 {
+            self.bias = error * JOINT_BIAS_FACTOR / dt;
+        }
+        self
+    }
+
+    /// Turns the row into a spring and damper, solved as a soft constraint
+    pub fn with_spring(mut self, error: Real, stiffness: Real, damping: Real, dt: Real) -> Self {
+        let denominator = dt * (damping + dt * stiffness);
+        if denominator > 0.0 {
+            self.softness = 1.0 / denominator;
+            self.bias = error * stiffness / (damping + dt * stiffness);
+        }
+        self
+    }
+
+    /// Drives the relative velocity along the row towards this
+    pub fn with_target_velocity(mut self, velocity: Real) -> Self {
+        self.bias = -velocity;
+        self
+    }
+
+    pub fn with_limits(mut self, min_impulse: Real, max_impulse: Real) -> Self {
+        self.min_impulse = min_impulse;
+        self.max_impulse = max_impulse;
+        self
+    }
+
+    /// Total impulse applied by this row so far this frame
+    pub fn impulse(&self) -> Real {
+        self.accumulated
+    }
+
+    pub(crate) fn prepare(&mut self, a: &Body, b: &Body) {
+        let mut k = self.softness;
+        if a.inv_mass > 0.0 {
+            k += a.inv_mass * self.linear_a.length_squared()
+                + self
+                    .angular_a
+                    .dot(a.inverse_inertia_tensor_world * self.angular_a);
+        }
+        if b.inv_mass > 0.0 {
+            k += b.inv_mass * self.linear_b.length_squared()
+                + self
+                    .angular_b
+                    .dot(b.inverse_inertia_tensor_world * self.angular_b);
+        }
+        self.effective_mass = if k > 0.0 { 1.0 / k } else { 0.0 };
+    }
+
+    /// Relative velocity along the row, J·v
+    pub(crate) fn velocity(&self, a: &Body, b: &Body) -> Real {
+        self.linear_a.dot(a.linear_velocity)
+            + self.angular_a.dot(a.angular_velocity)
+            + self.linear_b.dot(b.linear_velocity)
+            + self.angular_b.dot(b.angular_velocity)
+    }
+
+    pub(crate) fn solve(&mut self, a: &mut Body, b: &mut Body) {
+        let jv = self.velocity(a, b);
+        let lambda = -(jv + self.bias + self.softness * self.accumulated) * self.effective_mass;
+
+        // Clamp the total, not this iterations share, so later iterations can take some back
+        let old = self.accumulated;
+        self.accumulated = (old + lambda).clamp(self.min_impulse, self.max_impulse);
+        let lambda = self.accumulated - old;
+
+        a.apply_impulse_linear(self.linear_a * lambda);
+        a.apply_impulse_angular(self.angular_a * lambda);
+        b.apply_impulse_linear(self.linear_b * lambda);
+        b.apply_impulse_angular(self.angular_b * lambda);
+    }
+}
+
+/// Rows of one joint for this frame
+#[derive(Debug, Clone)]
+pub struct JointConstraint {
+    pub joint: Entity,
+    pub rows: Vec<JointRow>,
+}
+
+impl JointConstraint {
+    pub fn new(joint: Entity, mut rows: Vec<JointRow>, a: &SolverBody, b: &SolverBody) -> Self {
+        for row in rows.iter_mut() {
+            row.prepare(&a.body, &b.body);
+        }
+        JointConstraint { joint, rows }
+    }
+
+    /// Force and torque the joint applied to b this frame
+    pub fn applied_force(&self, dt: Real) -> (Real, Real) {
+        if dt <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let mut force = Vector::ZERO;
+        let mut torque = Vector::ZERO;
+        for row in self.rows.iter() {
+            if row.linear_b == Vector::ZERO {
+                torque += row.angular_b * row.accumulated;
+            } else {
+                force += row.linear_b * row.accumulated;
+            }
+        }
+        (force.length() / dt, torque.length() / dt)
+    }
+}
+
+/// Runs one iteration over every row of the joint
+pub fn solve_joint(rows: &mut [JointRow], a: &mut SolverBody, b: &mut SolverBody) {
+    for row in rows.iter_mut() {
+        row.solve(&mut a.body, &mut b.body);
+    }
+}
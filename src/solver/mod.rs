@@ -0,0 +1,260 @@
+mod contact;
+mod joint;
+
+pub use contact::*;
+pub use joint::*;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{Body, LockedAxes, Position, Quaternion, Vector};
+
+// Bitmask per body, constraints past this many colors end up in the serial batch
+const MAX_COLORS: u32 = 64;
+
+/// Copy of a body the solver works on, written back to the world once solving is done
+#[derive(Clone)]
+pub struct SolverBody {
+    pub entity: Entity,
+    pub body: Body,
+    pub translation: Vector,
+    /// Constraints only read this, it's not written back
+    pub rotation: Quaternion,
+    pub locked: LockedAxes,
+}
+
+/// The parts of a body a constraint can change
+#[derive(Debug, Clone, Copy)]
+pub struct SolverBodyState {
+    pub linear_velocity: Vector,
+    pub angular_velocity: Vector,
+    pub translation: Vector,
+}
+
+impl SolverBody {
+    pub fn state(&self) -> SolverBodyState {
+        SolverBodyState {
+            linear_velocity: self.body.linear_velocity,
+            angular_velocity: self.body.angular_velocity,
+            translation: self.translation,
+        }
+    }
+
+    /// Zeroes the velocities along locked axes, run after every constraint
+    pub fn lock(&mut self) {
+        self.locked.lock_velocities(&mut self.body);
+    }
+
+    pub fn set_state(&mut self, state: SolverBodyState) {
+        self.body.linear_velocity = state.linear_velocity;
+        self.body.angular_velocity = state.angular_velocity;
+        self.translation = state.translation;
+    }
+
+    /// Static bodies are never changed by the solver, so they dont limit batching
+    pub fn is_static(&self) -> bool {
+        self.body.inv_mass == 0.0
+    }
+}
+
+/// Bodies taking part in this frames constraints, each entity is copied out once
+#[derive(Default)]
+pub struct SolverBodies {
+    pub bodies: Vec<SolverBody>,
+    indices: HashMap<Entity, usize>,
+}
+
+impl SolverBodies {
+    /// Index of the entity, copying it in if needed. None if it has no body
+    pub fn index_of(
+        &mut self,
+        entity: Entity,
+        get: impl FnOnce(Entity) -> Option<(Body, Position, LockedAxes)>,
+    ) -> Option<usize> {
+        if let Some(&index) = self.indices.get(&entity) {
+            return Some(index);
+        }
+        let (body, position, locked) = get(entity)?;
+        self.bodies.push(SolverBody {
+            entity,
+            body,
+            translation: position.translation,
+            rotation: position.rotation,
+            locked,
+        });
+        self.indices.insert(entity, self.bodies.len() - 1);
+        Some(self.bodies.len() - 1)
+    }
+
+    /// Indices of both entities of a pair. None for self pairs or if either has no body
+    pub fn pair_of(
+        &mut self,
+        a: Entity,
+        b: Entity,
+        get: impl Fn(Entity) -> Option<(Body, Position, LockedAxes)>,
+    ) -> Option<(usize, usize)> {
+        if a == b {
+            return None;
+        }
+        let index_a = self.index_of(a, &get)?;
+        let index_b = self.index_of(b, &get)?;
+        Some((index_a, index_b))
+    }
+}
+
+/// Constraints split so no two in the same batch change the same body, letting each batch be
+/// solved in parallel
+pub struct ColoredBatches {
+    pub batches: Vec<Vec<usize>>,
+    /// Constraints that didnt fit any color, these have to be solved one at a time
+    pub serial: Vec<usize>,
+}
+
+/// Greedy graph coloring of the constraints, pairs are indices into bodies
+pub fn color_constraints(pairs: &[(usize, usize)], bodies: &[SolverBody]) -> ColoredBatches {
+    let mut masks = vec![0u64; bodies.len()];
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut serial = Vec::new();
+
+    for (i, &(a, b)) in pairs.iter().enumerate() {
+        let dynamic_a = !bodies[a].is_static();
+        let dynamic_b = !bodies[b].is_static();
+
+        let mut used = 0;
+        if dynamic_a {
+            used |= masks[a];
+        }
+        if dynamic_b {
+            used |= masks[b];
+        }
+
+        let color = (!used).trailing_zeros();
+        if color >= MAX_COLORS {
+            serial.push(i);
+            continue;
+        }
+
+        if dynamic_a {
+            masks[a] |= 1 << color;
+        }
+        if dynamic_b {
+            masks[b] |= 1 << color;
+        }
+        let color = color as usize;
+        if batches.len() <= color {
+            batches.resize_with(color + 1, Vec::new);
+        }
+        batches[color].push(i);
+    }
+
+    ColoredBatches { batches, serial }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        resolve_system,
+        test_util::{contact, run, spawn_ball, test_world, Rng},
+        Body, Contact, LockedAxes, Quaternion, Vector,
+    };
+
+    use super::{color_constraints, SolverBody, MAX_COLORS};
+
+    fn solver_bodies(
+        world: &mut World,
+        count: usize,
+        is_static: impl Fn(usize) -> bool,
+    ) -> Vec<SolverBody> {
+        (0..count)
+            .map(|i| SolverBody {
+                entity: world.spawn().id(),
+                body: Body {
+                    inv_mass: if is_static(i) { 0.0 } else { 1.0 },
+                    ..Default::default()
+                },
+                translation: Vector::ZERO,
+                rotation: Quaternion::IDENTITY,
+                locked: LockedAxes::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batches_never_share_a_dynamic_body() {
+        let mut world = World::new();
+        // Every fifth body is static, those can be in any number of constraints per batch
+        let bodies = solver_bodies(&mut world, 40, |i| i % 5 == 0);
+        let mut rng = Rng(0x6a09_e667_f3bc_c909);
+        let pairs = (0..400)
+            .map(|_| {
+                let a = rng.range(0.0, 40.0) as usize;
+                let b = (a + 1 + rng.range(0.0, 39.0) as usize) % 40;
+                (a, b)
+            })
+            .collect::<Vec<_>>();
+
+        let colored = color_constraints(&pairs, &bodies);
+        assert!(colored.serial.is_empty());
+        let mut seen = vec![0; pairs.len()];
+        for batch in colored.batches.iter() {
+            let mut used = vec![false; bodies.len()];
+            for &i in batch {
+                seen[i] += 1;
+                let (a, b) = pairs[i];
+                for body in [a, b] {
+                    if !bodies[body].is_static() {
+                        assert!(!used[body], "body {} twice in a batch", body);
+                        used[body] = true;
+                    }
+                }
+            }
+        }
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn past_max_colors_goes_serial() {
+        let mut world = World::new();
+        // Body 0 is in every constraint, static in the second run
+        for hub_static in [false, true] {
+            let bodies = solver_bodies(&mut world, 71, |i| i == 0 && hub_static);
+            let pairs = (1..71).map(|i| (0, i)).collect::<Vec<_>>();
+            let colored = color_constraints(&pairs, &bodies);
+            if hub_static {
+                assert_eq!(colored.batches.len(), 1);
+                assert!(colored.serial.is_empty());
+            } else {
+                assert_eq!(colored.batches.len(), MAX_COLORS as usize);
+                assert_eq!(colored.serial, (64..70).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn serial_constraints_are_solved() {
+        let mut world = test_world();
+        let hub = spawn_ball(&mut world, Vector::ZERO);
+        let others = (0..70)
+            .map(|_| {
+                let ball = spawn_ball(&mut world, Vector::X);
+                world.get_mut::<Body>(ball).unwrap().linear_velocity = Vector::X * -2.0;
+                ball
+            })
+            .collect::<Vec<_>>();
+        {
+            let mut contacts = world.get_resource_mut::<Events<Contact>>().unwrap();
+            for &ball in others.iter() {
+                contacts.send(contact(hub, ball));
+            }
+        }
+
+        run(&mut world, resolve_system);
+
+        // The last six only fit in the serial batch, they still get pushed back
+        for &ball in others.iter() {
+            let velocity = world.get::<Body>(ball).unwrap().linear_velocity;
+            assert!(velocity.x > -2.0, "{:?}", velocity);
+        }
+    }
+}
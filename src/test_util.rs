@@ -0,0 +1,173 @@
+//! Worlds and bodies shared by the unit tests
+
+use bevy::{
+    ecs::system::SystemState,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
+
+use crate::*;
+
+/// World with the resources and events the physics systems read
+pub fn test_world() -> World {
+    let mut world = World::new();
+    world.insert_resource(ComputeTaskPool(TaskPool::new()));
+    world.insert_resource(PhysicsConfig::default());
+    world.insert_resource(PhysicsTime { time: 1.0 / 60.0 });
+    world.insert_resource(ContactHooks::default());
+    world.insert_resource(Events::<BroadContact>::default());
+    world.insert_resource(Events::<Contact>::default());
+    world.insert_resource(Events::<ContactImpulse>::default());
+    world.insert_resource(Events::<JointBroken>::default());
+    world.insert_resource(Events::<CollisionStarted>::default());
+    world.insert_resource(Events::<CollisionEnded>::default());
+    world.insert_resource(CollisionPairs::default());
+    world.insert_resource(SweepAndPrune::default());
+    world.insert_resource(Bvh::default());
+    world.insert_resource(SpatialHashGrid::default());
+    world.insert_resource(BroadphaseSynced::default());
+    world
+}
+
+/// Unit mass sphere of radius 1, with its bounds already where it is
+pub fn spawn_ball(world: &mut World, translation: Vector) -> Entity {
+    world
+        .spawn()
+        .insert(Body {
+            mass: Mass::Value(1.0),
+            inv_mass: 1.0,
+            center_of_mass_world: translation,
+            ..Default::default()
+        })
+        .insert(ColliderType::Sphere)
+        .insert(ColliderSphere::new(1.0))
+        .insert(Aabb {
+            minimums: Vector::splat(-1.0),
+            maximums: Vector::splat(1.0),
+        })
+        .insert(GlobalAabb {
+            minimums: translation - Vector::ONE,
+            maximums: translation + Vector::ONE,
+        })
+        .insert(Position::from_translation(translation))
+        .insert(GlobalTransform::from_translation(from_vector(translation)))
+        .id()
+}
+
+/// Joint between a and b, both taken to be unrotated when it was made
+pub fn spawn_joint(
+    world: &mut World,
+    a: Entity,
+    b: Entity,
+    anchor_a: Vector,
+    anchor_b: Vector,
+    kind: JointKind,
+) -> Entity {
+    world
+        .spawn()
+        .insert(Joint {
+            body_a: a,
+            body_b: b,
+            anchor_a,
+            anchor_b,
+            kind,
+        })
+        .insert(JointState {
+            reference_rotation: Quaternion::IDENTITY,
+        })
+        .id()
+}
+
+/// Contact from a to b along X, overlapping by half a unit
+pub fn contact(a: Entity, b: Entity) -> Contact {
+    Contact {
+        a,
+        b,
+        world_point_a: Vector::ZERO,
+        world_point_b: Vector::X * -0.5,
+        local_point_a: Vector::ZERO,
+        local_point_b: Vector::ZERO,
+        normal: Vector::X,
+        separation_dist: -0.5,
+        time_of_impact: 0.0,
+        elasticity: 0.5,
+        friction: 0.5,
+        surface_velocity: Vector::ZERO,
+    }
+}
+
+/// Runs a single system once
+pub fn run<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(system);
+    stage.run(world);
+}
+
+/// Runs f with a [PhysicsQuery] on the world
+pub fn physics_query<R>(world: &mut World, f: impl FnOnce(&PhysicsQuery) -> R) -> R {
+    let mut state: SystemState<PhysicsQuery> = SystemState::new(world);
+    let query = state.get_mut(world);
+    f(&query)
+}
+
+/// Small xorshift generator, enough to scatter bodies without a rand dependency
+pub struct Rng(pub u64);
+
+impl Rng {
+    /// Uniform in [min, max)
+    pub fn range(&mut self, min: Real, max: Real) -> Real {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        min + (max - min) * ((self.0 >> 11) as Real / (1u64 << 53) as Real)
+    }
+
+    pub fn vector(&mut self, min: Vector, max: Vector) -> Vector {
+        Vector::new(
+            self.range(min.x, max.x),
+            self.range(min.y, max.y),
+            self.range(min.z, max.z),
+        )
+    }
+}
+
+/// Entity with nothing but world bounds, all the broad phase looks at
+pub fn spawn_bounds(world: &mut World, center: Vector, half_extents: Vector) -> Entity {
+    world
+        .spawn()
+        .insert(GlobalAabb {
+            minimums: center - half_extents,
+            maximums: center + half_extents,
+        })
+        .id()
+}
+
+/// Every pair of bounds overlapping by more than touching, smaller entity first and sorted
+pub fn brute_force_pairs(world: &mut World) -> Vec<(Entity, Entity)> {
+    let bounds = world
+        .query::<(Entity, &GlobalAabb)>()
+        .iter(world)
+        .map(|(entity, aabb)| (entity, aabb.minimums, aabb.maximums))
+        .collect::<Vec<_>>();
+    let mut pairs = Vec::new();
+    for (i, &(a, min_a, max_a)) in bounds.iter().enumerate() {
+        for &(b, min_b, max_b) in bounds.iter().skip(i + 1) {
+            if min_a.cmplt(max_b).all() && min_b.cmplt(max_a).all() {
+                pairs.push((a.min(b), a.max(b)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Takes the broad contacts sent so far, smaller entity first and sorted
+pub fn broad_pairs(world: &mut World) -> Vec<(Entity, Entity)> {
+    let mut events = world.get_resource_mut::<Events<BroadContact>>().unwrap();
+    let mut pairs = events
+        .drain()
+        .map(|contact| (contact.a.min(contact.b), contact.a.max(contact.b)))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+}